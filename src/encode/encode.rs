@@ -1,6 +1,7 @@
 use crate::encode::error::Error;
 use crate::encode::reader::BytesReader;
 use crate::encode::writer::BytesWriter;
+use std::convert::TryFrom;
 use std::str;
 
 pub trait Encode {
@@ -128,17 +129,18 @@ impl Decode for i64 {
     }
 }
 
+// usize is always encoded as 8 bytes so that encoded values are portable
+// between 32-bit targets (such as wasm32) and 64-bit targets.
 impl Encode for usize {
     fn encode(&self, w: &mut BytesWriter) {
-        w.write(&self.to_le_bytes());
+        (*self as u64).encode(w);
     }
 }
 
 impl Decode for usize {
     fn decode(r: &mut BytesReader) -> Result<Self, Error> {
-        let b = r.read(8)?;
-        let v: [u8; 8] = [b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]];
-        Ok(usize::from_le_bytes(v))
+        let v = u64::decode(r)?;
+        usize::try_from(v).map_err(|_| Error::InvalidFormat("usize out of range for target"))
     }
 }
 
@@ -287,6 +289,12 @@ mod tests {
         check_encode_and_decode(-1041230978056i64);
     }
 
+    #[test]
+    fn test_serialize_usize() {
+        check_encode_and_decode(1041230978usize);
+        assert_eq!(encode(&5usize).len(), 8);
+    }
+
     #[test]
     fn test_serialize_str_ref() {
         let s = &"abcd1234";
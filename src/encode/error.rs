@@ -3,3 +3,14 @@ pub enum Error {
     NotEnoughBytes,
     InvalidFormat(&'static str),
 }
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::NotEnoughBytes => write!(f, "not enough bytes to decode value"),
+            Error::InvalidFormat(msg) => write!(f, "invalid format: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -6,11 +6,8 @@ pub struct BytesReader<'a> {
 }
 
 impl<'a> BytesReader<'a> {
-    pub fn new(bytes: &'a [u8]) -> BytesReader {
-        BytesReader {
-            cursor: 0,
-            bytes: bytes,
-        }
+    pub fn new(bytes: &'a [u8]) -> BytesReader<'a> {
+        BytesReader { cursor: 0, bytes }
     }
 
     pub fn read(&mut self, n: usize) -> Result<&[u8], Error> {
@@ -3,7 +3,7 @@ pub struct BytesWriter<'a> {
 }
 
 impl<'a> BytesWriter<'a> {
-    pub fn new(buf: &'a mut Vec<u8>) -> BytesWriter {
+    pub fn new(buf: &'a mut Vec<u8>) -> BytesWriter<'a> {
         BytesWriter { buf }
     }
 
@@ -12,7 +12,7 @@ impl<'a> BytesWriter<'a> {
     }
 
     pub fn bytes(&self) -> &[u8] {
-        &self.buf
+        self.buf
     }
 }
 
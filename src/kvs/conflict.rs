@@ -0,0 +1,24 @@
+use crate::kvs::error::Error;
+use crate::kvs::txn::TxnId;
+
+/// Describes a conflict that failed a write or a commit,
+/// so that applications can tell which workloads contend with each other.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConflictEvent<S, K> {
+    /// Error returned to the transaction that hit the conflict.
+    pub error: Error,
+    pub keyspace_id: S,
+    pub key: K,
+    pub txn_id: TxnId,
+    pub txn_label: Option<String>,
+
+    /// Transaction on the other side of the conflict: the lock holder for a
+    /// write-write conflict, the latest reader for a read-write conflict,
+    /// or the committed writer for a phantom.  None if no longer known.
+    pub other_txn_id: Option<TxnId>,
+    pub other_txn_label: Option<String>,
+}
+
+/// Callback invoked on every conflict.  It runs on the thread of the failing
+/// operation, after the store has released its locks.
+pub type ConflictHook<S, K> = Box<dyn Fn(&ConflictEvent<S, K>) + Send + Sync>;
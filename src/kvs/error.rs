@@ -5,14 +5,76 @@ pub enum Error {
     UndefinedKeySpace,
     VersionNotFound,
     InvalidTxnId,
+    InvalidTimestamp,
+    SnapshotTooOld,
     ReadWriteConflict,
     WriteWriteConflict,
     PhantomDetected,
     EncodeError(EncodeError),
 }
 
+impl Error {
+    /// Whether the transaction failed because it conflicted with
+    /// a concurrent transaction, so retrying it may succeed.
+    pub fn is_conflict(&self) -> bool {
+        matches!(
+            self,
+            Error::ReadWriteConflict | Error::WriteWriteConflict | Error::PhantomDetected
+        )
+    }
+
+    /// Stable SQLSTATE-style code identifying the kind of error,
+    /// following PostgreSQL's assignments where one exists.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::ReadWriteConflict | Error::WriteWriteConflict | Error::PhantomDetected => {
+                "40001"
+            }
+            Error::InvalidTxnId => "25P01",
+            Error::InvalidTimestamp => "22023",
+            Error::SnapshotTooOld => "72000",
+            Error::EncodeError(_) => "XX001",
+            Error::UndefinedKeySpace | Error::VersionNotFound => "XX000",
+        }
+    }
+}
+
 impl From<EncodeError> for Error {
     fn from(err: EncodeError) -> Error {
         Error::EncodeError(err)
     }
 }
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::UndefinedKeySpace => write!(f, "keyspace is not defined"),
+            Error::VersionNotFound => write!(f, "version not found"),
+            Error::InvalidTxnId => write!(f, "transaction is not active"),
+            Error::InvalidTimestamp => {
+                write!(f, "timestamp is not before every active transaction")
+            }
+            Error::SnapshotTooOld => write!(f, "versions at timestamp have been vacuumed"),
+            Error::ReadWriteConflict => {
+                write!(f, "key was read by a later transaction")
+            }
+            Error::WriteWriteConflict => {
+                write!(f, "key is being written by another transaction")
+            }
+            Error::PhantomDetected => write!(
+                f,
+                "key read by the transaction was written by a concurrent transaction"
+            ),
+            Error::EncodeError(_) => write!(f, "could not encode or decode value"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::EncodeError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::kvs::key::Key;
+use std::collections::{BTreeMap, HashMap};
+
+/// Order in which keys of a cache-style keyspace are evicted.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EvictionPolicy {
+    /// Evict the key that was written least recently.
+    Fifo,
+
+    /// Evict the key that was read or written least recently.
+    Lru,
+}
+
+/// Tracks the eviction order of keys in a keyspace.
+pub struct EvictionQueue<K>
+where
+    K: Key,
+{
+    policy: EvictionPolicy,
+    max_keys: usize,
+    next_seq: u64,
+    seq_to_key: BTreeMap<u64, K>,
+    key_to_seq: HashMap<K, u64>,
+}
+
+impl<K> EvictionQueue<K>
+where
+    K: Key,
+{
+    pub fn new(policy: EvictionPolicy, max_keys: usize) -> EvictionQueue<K> {
+        EvictionQueue {
+            policy,
+            max_keys,
+            next_seq: 0,
+            seq_to_key: BTreeMap::new(),
+            key_to_seq: HashMap::new(),
+        }
+    }
+
+    pub fn max_keys(&self) -> usize {
+        self.max_keys
+    }
+
+    pub fn record_read(&mut self, key: &K) {
+        if self.policy == EvictionPolicy::Lru && self.key_to_seq.contains_key(key) {
+            self.push_back(key);
+        }
+    }
+
+    pub fn record_write(&mut self, key: &K) {
+        if self.policy == EvictionPolicy::Lru || !self.key_to_seq.contains_key(key) {
+            self.push_back(key);
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        if let Some(seq) = self.key_to_seq.remove(key) {
+            self.seq_to_key.remove(&seq);
+        }
+    }
+
+    /// Keys from the next to evict to the last.
+    pub fn iter(&self) -> impl Iterator<Item = &K> {
+        self.seq_to_key.values()
+    }
+
+    fn push_back(&mut self, key: &K) {
+        self.remove(key);
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.seq_to_key.insert(seq, key.clone());
+        self.key_to_seq.insert(key.clone(), seq);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(queue: &EvictionQueue<&'static str>) -> Vec<&'static str> {
+        queue.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_fifo_order() {
+        let mut queue = EvictionQueue::new(EvictionPolicy::Fifo, 2);
+        queue.record_write(&"a");
+        queue.record_write(&"b");
+        queue.record_read(&"a");
+        queue.record_write(&"a");
+        assert_eq!(order(&queue), vec!["a", "b"]);
+        queue.remove(&"a");
+        assert_eq!(order(&queue), vec!["b"]);
+    }
+
+    #[test]
+    fn test_lru_order() {
+        let mut queue = EvictionQueue::new(EvictionPolicy::Lru, 2);
+        queue.record_write(&"a");
+        queue.record_write(&"b");
+        queue.record_write(&"c");
+        queue.record_read(&"a");
+        queue.record_read(&"notexist");
+        assert_eq!(order(&queue), vec!["b", "c", "a"]);
+        queue.record_write(&"b");
+        assert_eq!(order(&queue), vec!["c", "a", "b"]);
+    }
+}
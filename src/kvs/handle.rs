@@ -0,0 +1,128 @@
+use crate::encode::{Decode, Encode};
+use crate::kvs::error::Error;
+use crate::kvs::key::Key;
+use crate::kvs::keyspace::KeySpaceId;
+use crate::kvs::store::Store;
+use crate::kvs::txn::TxnId;
+use std::marker::PhantomData;
+
+/// A keyspace whose values all have type `V`.
+/// Obtained from `Store::define_typed_keyspace()`, so every read decodes
+/// the same type that was written.
+pub struct KeySpaceHandle<'a, S, K, V>
+where
+    S: KeySpaceId,
+    K: Key,
+{
+    store: &'a Store<S, K>,
+    keyspace_id: S,
+    value_type: PhantomData<fn(V) -> V>,
+}
+
+impl<'a, S, K, V> KeySpaceHandle<'a, S, K, V>
+where
+    S: KeySpaceId,
+    K: Key,
+    V: Encode + Decode,
+{
+    pub(crate) fn new(store: &'a Store<S, K>, keyspace_id: S) -> Self {
+        KeySpaceHandle {
+            store,
+            keyspace_id,
+            value_type: PhantomData,
+        }
+    }
+
+    pub fn keyspace_id(&self) -> S {
+        self.keyspace_id
+    }
+
+    pub fn get(&self, txn_id: TxnId, key: &K) -> Result<Option<V>, Error> {
+        self.store.get(txn_id, self.keyspace_id, key)
+    }
+
+    pub fn set(&self, txn_id: TxnId, key: &K, val: &V) -> Result<(), Error> {
+        self.store.set(txn_id, self.keyspace_id, key, val)
+    }
+
+    pub fn delete(&self, txn_id: TxnId, key: &K) -> Result<(), Error> {
+        self.store.delete(txn_id, self.keyspace_id, key)
+    }
+}
+
+impl<S, K, V> Clone for KeySpaceHandle<'_, S, K, V>
+where
+    S: KeySpaceId,
+    K: Key,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S, K, V> Copy for KeySpaceHandle<'_, S, K, V>
+where
+    S: KeySpaceId,
+    K: Key,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Eq, PartialEq, Clone, Copy, Hash)]
+    enum TestKeySpace {
+        Names,
+        Counts,
+    }
+    impl KeySpaceId for TestKeySpace {}
+
+    #[test]
+    fn test_typed_keyspaces() {
+        let store = Store::new();
+        let names = store.define_typed_keyspace::<String>(TestKeySpace::Names);
+        let counts = store.define_typed_keyspace::<u64>(TestKeySpace::Counts);
+
+        let result: Result<(), Error> = store.with_txn(|txn_id| {
+            names.set(txn_id, &"foo", &"bar".to_string())?;
+            counts.set(txn_id, &"foo", &7)
+        });
+        assert_eq!(result, Ok(()));
+
+        let result = store.with_txn(|txn_id| {
+            let name = names.get(txn_id, &"foo")?;
+            let count = counts.get(txn_id, &"foo")?;
+            Ok::<_, Error>((name, count))
+        });
+        assert_eq!(result, Ok((Some("bar".to_string()), Some(7))));
+
+        let result: Result<Option<u64>, Error> = store.with_txn(|txn_id| {
+            counts.delete(txn_id, &"foo")?;
+            counts.get(txn_id, &"foo")
+        });
+        assert_eq!(result, Ok(None));
+    }
+
+    #[test]
+    fn test_typed_and_dynamic_access_share_keyspace() {
+        let store = Store::new();
+        let names = store.define_typed_keyspace::<String>(TestKeySpace::Names);
+
+        let result: Result<(), Error> =
+            store.with_txn(|txn_id| store.set(txn_id, names.keyspace_id(), &"foo", &"bar"));
+        assert_eq!(result, Ok(()));
+
+        let result = store.with_txn(|txn_id| names.get(txn_id, &"foo"));
+        assert_eq!(result, Ok(Some("bar".to_string())));
+    }
+
+    #[test]
+    #[should_panic(expected = "Keyspace was already defined with a type other than u64")]
+    fn test_redefine_with_other_type() {
+        let store: Store<TestKeySpace, &str> = Store::new();
+        store.define_typed_keyspace::<String>(TestKeySpace::Names);
+        store.define_typed_keyspace::<String>(TestKeySpace::Names);
+        store.define_typed_keyspace::<u64>(TestKeySpace::Names);
+    }
+}
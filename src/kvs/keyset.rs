@@ -54,7 +54,8 @@ where
         }
     }
 
-    pub fn overlaps(&self, other: &KeySet<S, K>) -> bool {
+    /// Find a key that is in both this set and `other`, if any.
+    pub fn find_overlap(&self, other: &KeySet<S, K>) -> Option<(S, K)> {
         let keyspace_map = self
             .keyspace_map
             .lock()
@@ -66,12 +67,12 @@ where
                 .lock()
                 .expect("Could not acquire lock on other keyspace map");
             if let Some(other_keyset) = other_keyspace_map.get(keyspace_id) {
-                if !keyset.is_disjoint(other_keyset) {
-                    return true;
+                if let Some(key) = keyset.intersection(other_keyset).next() {
+                    return Some((*keyspace_id, key.clone()));
                 }
             }
         }
 
-        return false;
+        None
     }
 }
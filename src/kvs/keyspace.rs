@@ -1,16 +1,60 @@
 use crate::encode::{Decode, Encode};
 use crate::kvs::error::Error;
+use crate::kvs::eviction::{EvictionPolicy, EvictionQueue};
 use crate::kvs::key::Key;
 use crate::kvs::txn::TxnId;
+use crate::kvs::verify::Inconsistency;
 use crate::kvs::version::{Version, VersionId, VersionTable};
 use core::hash::Hash;
+use std::any::TypeId;
 use std::collections::{HashMap, HashSet};
-use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
 
 /// Uniquely identify a keyspace.
 /// The concrete implementation is defined by callers of this module.
 pub trait KeySpaceId: Hash + Eq + Copy {}
 
+/// Approximate size of a keyspace, including versions that are
+/// no longer visible to any transaction.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct KeySpaceStats {
+    /// Keys with at least one version, including deleted and uncommitted keys.
+    pub num_keys: usize,
+
+    /// Versions across all keys, including superseded and aborted versions.
+    pub num_versions: usize,
+
+    /// Encoded bytes of all version values.
+    pub num_value_bytes: usize,
+
+    /// Approximate heap bytes held by the keyspace's key map and version table,
+    /// including spare capacity.  Does not count heap data owned by keys.
+    pub num_allocated_bytes: usize,
+}
+
+/// Versions unlinked by vacuuming.  Unlinked versions are unreachable
+/// but still allocated until `Store::shrink_to_fit()` compacts the keyspace.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct VacuumStats {
+    /// Keys removed because their latest version is a deletion nobody can see past.
+    pub removed_keys: usize,
+
+    /// Versions unlinked from their version chains.
+    pub unlinked_versions: usize,
+
+    /// Encoded value bytes held by the unlinked versions.
+    pub unlinked_bytes: usize,
+}
+
+impl VacuumStats {
+    pub fn add(&mut self, other: VacuumStats) {
+        self.removed_keys += other.removed_keys;
+        self.unlinked_versions += other.unlinked_versions;
+        self.unlinked_bytes += other.unlinked_bytes;
+    }
+}
+
 /// Stores key-value pairs in an application-defined space of keys.
 pub struct KeySpace<K>
 where
@@ -18,6 +62,15 @@ where
 {
     key_map: RwLock<HashMap<K, VersionId>>,
     version_tbl: VersionTable,
+    skip_identical_writes: AtomicBool,
+
+    // Set once an eviction policy is installed, so keyspaces without one
+    // never touch the eviction queue lock.
+    has_eviction_policy: AtomicBool,
+    eviction_queue: Mutex<Option<EvictionQueue<K>>>,
+
+    // Type of every value, if the keyspace was defined as typed.
+    value_type: Mutex<Option<TypeId>>,
 }
 
 impl<K> KeySpace<K>
@@ -28,6 +81,10 @@ where
         KeySpace {
             key_map: RwLock::new(HashMap::new()),
             version_tbl: VersionTable::new(),
+            skip_identical_writes: AtomicBool::new(false),
+            has_eviction_policy: AtomicBool::new(false),
+            eviction_queue: Mutex::new(None),
+            value_type: Mutex::new(None),
         }
     }
 
@@ -44,11 +101,26 @@ where
             None => Ok(None),
             Some(version_id) => {
                 let val_opt = self.version_tbl.retrieve(txn_id, *version_id)?;
+                self.with_eviction_queue(|queue| queue.record_read(key));
                 Ok(val_opt)
             }
         }
     }
 
+    pub fn get_as_of<V>(&self, ts: TxnId, key: &K) -> Result<Option<V>, Error>
+    where
+        V: Decode,
+    {
+        let key_map = self
+            .key_map
+            .read()
+            .expect("Could not acquire read lock for key map");
+        match key_map.get(key) {
+            None => Ok(None),
+            Some(version_id) => self.version_tbl.retrieve_as_of(ts, *version_id),
+        }
+    }
+
     pub fn set<V>(&self, txn_id: TxnId, key: &K, val: &V) -> Result<(), Error>
     where
         V: Encode,
@@ -56,10 +128,241 @@ where
         self.upsert_uncommitted_version(txn_id, key, Version::Value(val))
     }
 
+    /// Record that every value has type `value_type`, unless the keyspace
+    /// was already defined with a type.  Returns whether the types agree.
+    pub fn set_value_type(&self, value_type: TypeId) -> bool {
+        *self
+            .value_type
+            .lock()
+            .expect("Could not acquire lock on value type")
+            .get_or_insert(value_type)
+            == value_type
+    }
+
+    pub fn set_skip_identical_writes(&self, enabled: bool) {
+        self.skip_identical_writes.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Whether a write of `val` can be skipped because the version
+    /// visible to the txn already holds it.  If so, the visible version
+    /// is read instead, and the caller must record the read.
+    pub fn can_skip_write<V>(&self, txn_id: TxnId, key: &K, val: &V) -> bool
+    where
+        V: Encode,
+    {
+        if !self.skip_identical_writes.load(Ordering::SeqCst) {
+            return false;
+        }
+        let key_map = self
+            .key_map
+            .read()
+            .expect("Could not acquire read lock for key map");
+        key_map.get(key).is_some_and(|version_id| {
+            self.version_tbl
+                .visible_value_equals(txn_id, *version_id, val)
+        })
+    }
+
+    /// Bound the number of keys, evicting committed keys in the order
+    /// given by `policy`.  Keys already present are queued in arbitrary order.
+    pub fn set_eviction_policy(&self, policy: EvictionPolicy, max_keys: usize) {
+        let key_map = self
+            .key_map
+            .read()
+            .expect("Could not acquire read lock for key map");
+        let mut queue = EvictionQueue::new(policy, max_keys);
+        for key in key_map.keys() {
+            queue.record_write(key);
+        }
+        *self
+            .eviction_queue
+            .lock()
+            .expect("Could not acquire lock on eviction queue") = Some(queue);
+        self.has_eviction_policy.store(true, Ordering::SeqCst);
+    }
+
+    pub fn has_eviction_policy(&self) -> bool {
+        self.has_eviction_policy.load(Ordering::SeqCst)
+    }
+
+    /// Evict keys over the limit whose latest version every transaction
+    /// after `horizon` could see and none of them has read yet, skipping
+    /// keys with any version visible at `snapshot_ts`, the newest pinned
+    /// snapshot.  Returns the number of keys evicted, which may leave the
+    /// keyspace over its limit if the remaining keys are still in use.
+    pub fn evict(&self, horizon: TxnId, snapshot_ts: Option<TxnId>) -> usize {
+        if !self.has_eviction_policy() {
+            return 0;
+        }
+
+        // Check the limit under the read lock, so commits that leave the
+        // keyspace within its limit do not block readers.
+        {
+            let key_map = self
+                .key_map
+                .read()
+                .expect("Could not acquire read lock for key map");
+            let is_over_limit = self
+                .eviction_queue
+                .lock()
+                .expect("Could not acquire lock on eviction queue")
+                .as_ref()
+                .is_some_and(|queue| key_map.len() > queue.max_keys());
+            if !is_over_limit {
+                return 0;
+            }
+        }
+
+        let mut key_map = self
+            .key_map
+            .write()
+            .expect("Could not acquire write lock for key map");
+        let mut eviction_queue = self
+            .eviction_queue
+            .lock()
+            .expect("Could not acquire lock on eviction queue");
+        let queue = match eviction_queue.as_mut() {
+            None => return 0,
+            Some(queue) => queue,
+        };
+
+        let num_over = key_map.len().saturating_sub(queue.max_keys());
+        let victims: Vec<K> = queue
+            .iter()
+            .filter(|key| {
+                key_map.get(*key).is_some_and(|version_id| {
+                    self.version_tbl
+                        .is_evictable(*version_id, horizon, snapshot_ts)
+                })
+            })
+            .take(num_over)
+            .cloned()
+            .collect();
+        for key in victims.iter() {
+            key_map.remove(key);
+            queue.remove(key);
+        }
+        victims.len()
+    }
+
+    fn with_eviction_queue<F>(&self, f: F)
+    where
+        F: FnOnce(&mut EvictionQueue<K>),
+    {
+        if !self.has_eviction_policy() {
+            return;
+        }
+        if let Some(queue) = self
+            .eviction_queue
+            .lock()
+            .expect("Could not acquire lock on eviction queue")
+            .as_mut()
+        {
+            f(queue)
+        }
+    }
+
+    pub fn conflicting_txn(&self, key: &K, err: &Error) -> Option<TxnId> {
+        let key_map = self
+            .key_map
+            .read()
+            .expect("Could not acquire read lock for key map");
+        key_map
+            .get(key)
+            .and_then(|version_id| self.version_tbl.conflicting_txn(*version_id, err))
+    }
+
+    /// Take the write lock on the latest version of `key` without writing it.
+    /// Returns whether the lock was newly acquired; absent keys are not locked.
+    pub fn lock(&self, txn_id: TxnId, key: &K) -> Result<bool, Error> {
+        let key_map = self
+            .key_map
+            .read()
+            .expect("Could not acquire read lock for key map");
+        match key_map.get(key) {
+            Some(version_id) => self.version_tbl.lock(txn_id, *version_id),
+            None => Ok(false),
+        }
+    }
+
+    pub fn unlock_keys(&self, txn_id: TxnId, keyset: &HashSet<K>) {
+        let key_map = self
+            .key_map
+            .read()
+            .expect("Could not acquire read lock for key map");
+
+        for key in keyset.iter() {
+            if let Some(version_id) = key_map.get(key) {
+                self.version_tbl.unlock(txn_id, *version_id);
+            }
+        }
+    }
+
     pub fn delete(&self, txn_id: TxnId, key: &K) -> Result<(), Error> {
         self.upsert_uncommitted_version::<&[u8]>(txn_id, key, Version::Deleted)
     }
 
+    pub fn stats(&self) -> KeySpaceStats {
+        let (num_keys, key_map_capacity) = {
+            let key_map = self
+                .key_map
+                .read()
+                .expect("Could not acquire read lock for key map");
+            (key_map.len(), key_map.capacity())
+        };
+        let key_map_bytes =
+            key_map_capacity * (std::mem::size_of::<K>() + std::mem::size_of::<VersionId>());
+        KeySpaceStats {
+            num_keys,
+            num_versions: self.version_tbl.num_versions(),
+            num_value_bytes: self.version_tbl.num_value_bytes(),
+            num_allocated_bytes: key_map_bytes + self.version_tbl.num_allocated_bytes(),
+        }
+    }
+
+    /// Drop versions that are no longer reachable, such as aborted and
+    /// vacuumed versions, and release spare capacity.
+    pub fn shrink_to_fit(&self) {
+        let mut key_map = self
+            .key_map
+            .write()
+            .expect("Could not acquire write lock for key map");
+
+        // Every access to the version table holds the key map lock,
+        // so holding it exclusively makes it safe to renumber versions.
+        self.version_tbl.compact(key_map.values_mut());
+        key_map.shrink_to_fit();
+    }
+
+    pub fn vacuum(&self, horizon: TxnId) -> VacuumStats {
+        let mut key_map = self
+            .key_map
+            .write()
+            .expect("Could not acquire write lock for key map");
+
+        let mut stats = VacuumStats::default();
+        key_map.retain(|key, version_id| {
+            let (remove_key, num_versions, num_bytes) =
+                self.version_tbl.vacuum_chain(*version_id, horizon);
+            stats.unlinked_versions += num_versions;
+            stats.unlinked_bytes += num_bytes;
+            if remove_key {
+                stats.removed_keys += 1;
+                self.with_eviction_queue(|queue| queue.remove(key));
+            }
+            !remove_key
+        });
+        stats
+    }
+
+    pub fn verify(&self) -> Vec<Inconsistency> {
+        let key_map = self
+            .key_map
+            .read()
+            .expect("Could not acquire read lock for key map");
+        self.version_tbl.verify_chains(key_map.values().copied())
+    }
+
     pub fn commit_keys(&self, keyset: &HashSet<K>) {
         let key_map = self
             .key_map
@@ -83,6 +386,7 @@ where
             match self.version_tbl.abort(*version_id) {
                 None => {
                     key_map.remove(key);
+                    self.with_eviction_queue(|queue| queue.remove(key));
                 }
                 Some(prev_version_id) => {
                     key_map.insert(key.clone(), prev_version_id);
@@ -109,7 +413,6 @@ where
                 // key doesn't already exist, so insert a new version
                 let version_id = self.version_tbl.append_first_version(txn_id, version);
                 key_map.insert(key.clone(), version_id);
-                Ok(())
             }
             Some(v) => {
                 // key already exists, so insert a new version after the previous version
@@ -117,8 +420,9 @@ where
                 *v = self
                     .version_tbl
                     .append_next_version(txn_id, prev_version_id, version)?;
-                Ok(())
             }
         }
+        self.with_eviction_queue(|queue| queue.record_write(key));
+        Ok(())
     }
 }
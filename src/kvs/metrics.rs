@@ -0,0 +1,154 @@
+use crate::kvs::error::Error;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters of store activity since the store was created.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct StoreMetrics {
+    pub txns_begun: u64,
+    pub txns_committed: u64,
+
+    /// Transactions aborted by the caller or by failed commit validation.
+    pub txns_aborted: u64,
+
+    pub read_write_conflicts: u64,
+    pub write_write_conflicts: u64,
+    pub phantoms_detected: u64,
+}
+
+impl StoreMetrics {
+    /// Render the counters in the Prometheus text exposition format.
+    pub fn to_prometheus_text(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            (
+                "otter_txns_begun_total",
+                "Transactions begun.",
+                self.txns_begun,
+            ),
+            (
+                "otter_txns_committed_total",
+                "Transactions committed.",
+                self.txns_committed,
+            ),
+            (
+                "otter_txns_aborted_total",
+                "Transactions aborted.",
+                self.txns_aborted,
+            ),
+        ];
+        for (name, help, val) in counters.iter() {
+            writeln!(out, "# HELP {} {}", name, help).expect("Could not write metric");
+            writeln!(out, "# TYPE {} counter", name).expect("Could not write metric");
+            writeln!(out, "{} {}", name, val).expect("Could not write metric");
+        }
+
+        let name = "otter_conflicts_total";
+        writeln!(
+            out,
+            "# HELP {} Conflicts between transactions, by kind.",
+            name
+        )
+        .expect("Could not write metric");
+        writeln!(out, "# TYPE {} counter", name).expect("Could not write metric");
+        let conflicts = [
+            ("read_write", self.read_write_conflicts),
+            ("write_write", self.write_write_conflicts),
+            ("phantom", self.phantoms_detected),
+        ];
+        for (kind, val) in conflicts.iter() {
+            writeln!(out, "{}{{kind=\"{}\"}} {}", name, kind, val).expect("Could not write metric");
+        }
+        out
+    }
+}
+
+#[derive(Default)]
+pub struct MetricsRecorder {
+    txns_begun: AtomicU64,
+    txns_committed: AtomicU64,
+    txns_aborted: AtomicU64,
+    read_write_conflicts: AtomicU64,
+    write_write_conflicts: AtomicU64,
+    phantoms_detected: AtomicU64,
+}
+
+impl MetricsRecorder {
+    pub fn record_begin(&self) {
+        self.txns_begun.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_commit<T>(&self, result: &Result<T, Error>) {
+        match result {
+            Ok(_) => self.txns_committed.fetch_add(1, Ordering::Relaxed),
+            // Only a failed validation aborts the txn.  Other errors, such as
+            // an ID that is not active, leave no txn behind to count.
+            Err(err) if err.is_conflict() => {
+                self.record_error(err);
+                self.txns_aborted.fetch_add(1, Ordering::Relaxed)
+            }
+            Err(_) => return,
+        };
+    }
+
+    pub fn record_abort<T>(&self, result: &Result<T, Error>) {
+        if result.is_ok() {
+            self.txns_aborted.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_error(&self, err: &Error) {
+        let counter = match err {
+            Error::ReadWriteConflict => &self.read_write_conflicts,
+            Error::WriteWriteConflict => &self.write_write_conflicts,
+            Error::PhantomDetected => &self.phantoms_detected,
+            _ => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> StoreMetrics {
+        StoreMetrics {
+            txns_begun: self.txns_begun.load(Ordering::Relaxed),
+            txns_committed: self.txns_committed.load(Ordering::Relaxed),
+            txns_aborted: self.txns_aborted.load(Ordering::Relaxed),
+            read_write_conflicts: self.read_write_conflicts.load(Ordering::Relaxed),
+            write_write_conflicts: self.write_write_conflicts.load(Ordering::Relaxed),
+            phantoms_detected: self.phantoms_detected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prometheus_text() {
+        let metrics = StoreMetrics {
+            txns_begun: 3,
+            txns_committed: 1,
+            txns_aborted: 2,
+            read_write_conflicts: 0,
+            write_write_conflicts: 1,
+            phantoms_detected: 1,
+        };
+        let expected = "\
+# HELP otter_txns_begun_total Transactions begun.
+# TYPE otter_txns_begun_total counter
+otter_txns_begun_total 3
+# HELP otter_txns_committed_total Transactions committed.
+# TYPE otter_txns_committed_total counter
+otter_txns_committed_total 1
+# HELP otter_txns_aborted_total Transactions aborted.
+# TYPE otter_txns_aborted_total counter
+otter_txns_aborted_total 2
+# HELP otter_conflicts_total Conflicts between transactions, by kind.
+# TYPE otter_conflicts_total counter
+otter_conflicts_total{kind=\"read_write\"} 0
+otter_conflicts_total{kind=\"write_write\"} 1
+otter_conflicts_total{kind=\"phantom\"} 1
+";
+        assert_eq!(metrics.to_prometheus_text(), expected);
+    }
+}
@@ -1,13 +1,28 @@
+mod conflict;
 mod error;
+mod eviction;
+mod handle;
 mod key;
 mod keyset;
 mod keyspace;
+mod metrics;
+mod snapshot;
 mod store;
+pub mod testing;
 mod txn;
+mod typed;
+mod verify;
 mod version;
 
+pub use conflict::{ConflictEvent, ConflictHook};
 pub use error::Error;
+pub use eviction::EvictionPolicy;
+pub use handle::KeySpaceHandle;
 pub use key::Key;
-pub use keyspace::KeySpaceId;
+pub use keyspace::{KeySpaceId, KeySpaceStats, VacuumStats};
+pub use metrics::StoreMetrics;
+pub use snapshot::Snapshot;
 pub use store::Store;
-pub use txn::TxnId;
+pub use txn::{TxnId, TxnInfo};
+pub use typed::{Record, RecordHandle};
+pub use verify::Inconsistency;
@@ -0,0 +1,180 @@
+use crate::encode::Decode;
+use crate::kvs::error::Error;
+use crate::kvs::key::Key;
+use crate::kvs::keyspace::KeySpaceId;
+use crate::kvs::store::Store;
+use crate::kvs::txn::TxnId;
+
+/// A consistent, read-only view of a store at a pinned timestamp.
+/// Created with `Store::snapshot()`.
+pub struct Snapshot<'a, S, K>
+where
+    S: KeySpaceId,
+    K: Key,
+{
+    store: &'a Store<S, K>,
+
+    // None if the snapshot was taken before any transaction began,
+    // in which case nothing is visible.
+    ts: Option<TxnId>,
+}
+
+impl<'a, S, K> Snapshot<'a, S, K>
+where
+    S: KeySpaceId,
+    K: Key,
+{
+    pub(crate) fn new(store: &'a Store<S, K>, ts: Option<TxnId>) -> Self {
+        Snapshot { store, ts }
+    }
+
+    /// Timestamp the snapshot reads at, usable with `Store::get_as_of()`.
+    pub fn ts(&self) -> Option<TxnId> {
+        self.ts
+    }
+
+    pub fn get<V>(&self, keyspace_id: S, key: &K) -> Result<Option<V>, Error>
+    where
+        V: Decode,
+    {
+        match self.ts {
+            Some(ts) => self.store.read_as_of(ts, keyspace_id, key),
+            None => self.store.check_keyspace_defined(keyspace_id).map(|_| None),
+        }
+    }
+}
+
+impl<S, K> Drop for Snapshot<'_, S, K>
+where
+    S: KeySpaceId,
+    K: Key,
+{
+    fn drop(&mut self) {
+        if let Some(ts) = self.ts {
+            self.store.unpin_snapshot(ts);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kvs::{EvictionPolicy, VacuumStats};
+
+    #[derive(Eq, PartialEq, Clone, Copy, Hash)]
+    struct TestKeySpace {}
+    impl KeySpaceId for TestKeySpace {}
+
+    fn set(store: &Store<TestKeySpace, &'static str>, key: &'static str, val: &str) {
+        let result: Result<(), Error> =
+            store.with_txn(|txn_id| store.set(txn_id, TestKeySpace {}, &key, &val));
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_snapshot_before_any_txn() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        let snapshot = store.snapshot();
+        set(&store, "foo", "bar");
+        assert_eq!(snapshot.ts(), None);
+        assert_eq!(snapshot.get::<String>(TestKeySpace {}, &"foo"), Ok(None));
+    }
+
+    #[test]
+    fn test_snapshot_ignores_later_writes() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        set(&store, "foo", "bar");
+
+        let snapshot = store.snapshot();
+        set(&store, "foo", "baz");
+        set(&store, "qux", "quux");
+        assert_eq!(
+            snapshot.get(TestKeySpace {}, &"foo"),
+            Ok(Some("bar".to_string()))
+        );
+        assert_eq!(snapshot.get::<String>(TestKeySpace {}, &"qux"), Ok(None));
+    }
+
+    #[test]
+    fn test_snapshot_excludes_active_txns() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        set(&store, "foo", "bar");
+
+        let txn_id = store.begin_txn();
+        store
+            .set(txn_id, TestKeySpace {}, &"foo", &"baz")
+            .expect("Could not set key");
+        let snapshot = store.snapshot();
+        store.commit_txn(txn_id).expect("Could not commit txn");
+
+        assert_eq!(
+            snapshot.get(TestKeySpace {}, &"foo"),
+            Ok(Some("bar".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_snapshot_pins_vacuum() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        set(&store, "foo", "bar");
+
+        let snapshot = store.snapshot();
+        set(&store, "foo", "baz");
+        let stats = store.vacuum(TestKeySpace {}).expect("Could not vacuum");
+        assert_eq!(stats, VacuumStats::default());
+        assert_eq!(
+            snapshot.get(TestKeySpace {}, &"foo"),
+            Ok(Some("bar".to_string()))
+        );
+
+        drop(snapshot);
+        let stats = store.vacuum(TestKeySpace {}).expect("Could not vacuum");
+        assert_eq!(stats.unlinked_versions, 1);
+    }
+
+    #[test]
+    fn test_snapshot_pins_eviction() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        store
+            .set_eviction_policy(TestKeySpace {}, EvictionPolicy::Fifo, 1)
+            .expect("Could not set eviction policy");
+        set(&store, "a", "1");
+
+        let snapshot = store.snapshot();
+        set(&store, "b", "2");
+        assert_eq!(
+            snapshot.get(TestKeySpace {}, &"a"),
+            Ok(Some("1".to_string()))
+        );
+        assert_eq!(
+            store.get_as_of::<String>(
+                snapshot.ts().expect("Snapshot has no timestamp"),
+                TestKeySpace {},
+                &"a"
+            ),
+            Err(Error::SnapshotTooOld)
+        );
+
+        // "b" was evicted instead, since the snapshot cannot see it.
+        let result: Result<Option<String>, Error> =
+            store.with_txn(|txn_id| store.get(txn_id, TestKeySpace {}, &"a"));
+        assert_eq!(result, Ok(Some("1".to_string())));
+        let result: Result<Option<String>, Error> =
+            store.with_txn(|txn_id| store.get(txn_id, TestKeySpace {}, &"b"));
+        assert_eq!(result, Ok(None));
+    }
+
+    #[test]
+    fn test_snapshot_undefined_keyspace() {
+        let store: Store<TestKeySpace, &str> = Store::new();
+        assert_eq!(
+            store.snapshot().get::<String>(TestKeySpace {}, &"foo"),
+            Err(Error::UndefinedKeySpace)
+        );
+    }
+}
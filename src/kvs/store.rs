@@ -1,10 +1,19 @@
 use crate::encode::{Decode, Encode};
+use crate::kvs::conflict::{ConflictEvent, ConflictHook};
 use crate::kvs::error::Error;
+use crate::kvs::eviction::EvictionPolicy;
+use crate::kvs::handle::KeySpaceHandle;
 use crate::kvs::key::Key;
-use crate::kvs::keyspace::{KeySpace, KeySpaceId};
-use crate::kvs::txn::{TxnId, TxnManager};
-use std::collections::{HashMap, HashSet};
-use std::sync::RwLock;
+use crate::kvs::keyspace::{KeySpace, KeySpaceId, KeySpaceStats, VacuumStats};
+use crate::kvs::metrics::{MetricsRecorder, StoreMetrics};
+use crate::kvs::snapshot::Snapshot;
+use crate::kvs::txn::{TxnId, TxnInfo, TxnManager};
+use crate::kvs::typed::{Record, RecordHandle};
+use crate::kvs::verify::Inconsistency;
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
 
 pub struct Store<S, K>
 where
@@ -13,6 +22,29 @@ where
 {
     txn_manager: TxnManager<S, K>,
     keyspace_map: RwLock<HashMap<S, KeySpace<K>>>,
+
+    // Versions visible only before this timestamp may have been vacuumed.
+    gc_horizon: AtomicUsize,
+
+    // Number of open snapshots at each read timestamp.
+    // Vacuum keeps the versions visible to the oldest one.
+    pinned_snapshots: Mutex<BTreeMap<TxnId, usize>>,
+
+    conflict_hook: RwLock<Option<ConflictHook<S, K>>>,
+    metrics: MetricsRecorder,
+
+    // Set once any keyspace has an eviction policy, so commits can skip eviction.
+    has_eviction_policy: AtomicBool,
+}
+
+impl<S, K> Default for Store<S, K>
+where
+    S: KeySpaceId,
+    K: Key,
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<S, K> Store<S, K>
@@ -24,6 +56,11 @@ where
         Store {
             txn_manager: TxnManager::new(),
             keyspace_map: RwLock::new(HashMap::new()),
+            gc_horizon: AtomicUsize::new(0),
+            pinned_snapshots: Mutex::new(BTreeMap::new()),
+            conflict_hook: RwLock::new(None),
+            metrics: MetricsRecorder::default(),
+            has_eviction_policy: AtomicBool::new(false),
         }
     }
 
@@ -38,11 +75,70 @@ where
             .or_insert_with(KeySpace::new);
     }
 
+    /// Define a keyspace whose values all have type `V`, returning a handle
+    /// that reads and writes only that type.  The keyspace can still be
+    /// accessed through the untyped methods of the store.
+    /// Panics if the keyspace was already defined with a different type.
+    pub fn define_typed_keyspace<V>(&self, keyspace_id: S) -> KeySpaceHandle<'_, S, K, V>
+    where
+        V: Encode + Decode + 'static,
+    {
+        self.define_keyspace_of_type::<V>(keyspace_id);
+        KeySpaceHandle::new(self, keyspace_id)
+    }
+
+    /// Define a keyspace of records of type `R`, each stored under the
+    /// key it reports through `Record::key()`.
+    /// Panics if the keyspace was already defined with a different type.
+    pub fn define_record_keyspace<R>(&self, keyspace_id: S) -> RecordHandle<'_, S, K, R>
+    where
+        R: Record<K> + 'static,
+    {
+        self.define_keyspace_of_type::<R>(keyspace_id);
+        RecordHandle::new(self, keyspace_id)
+    }
+
+    fn define_keyspace_of_type<V: 'static>(&self, keyspace_id: S) {
+        let mut keyspace_map = self
+            .keyspace_map
+            .write()
+            .expect("Could not acquire write lock on keyspace map");
+
+        let is_same_type = keyspace_map
+            .entry(keyspace_id)
+            .or_insert_with(KeySpace::new)
+            .set_value_type(TypeId::of::<V>());
+        assert!(
+            is_same_type,
+            "Keyspace was already defined with a type other than {}",
+            std::any::type_name::<V>()
+        );
+    }
+
+    pub fn keyspace_stats(&self, keyspace_id: S) -> Result<KeySpaceStats, Error> {
+        self.keyspace_map
+            .read()
+            .expect("Could not acquire read lock on keyspace map")
+            .get(&keyspace_id)
+            .ok_or(Error::UndefinedKeySpace)
+            .map(|ks| ks.stats())
+    }
+
     /// Execute `f` within a transaction, committing on success
     /// and aborting on failure.  `f` should NOT itself call `with_txn()`
     /// since doing so will begin and commit/abort a new transaction
     /// that might conflict with the current transaction.
-    pub fn with_txn<F, R, E>(&self, mut f: F) -> Result<R, E>
+    pub fn with_txn<F, R, E>(&self, f: F) -> Result<R, E>
+    where
+        E: From<Error>,
+        F: FnMut(TxnId) -> Result<R, E>,
+    {
+        self.with_txn_info(f).map(|(result, _)| result)
+    }
+
+    /// Like `with_txn()`, but also returns the begin and commit timestamps
+    /// of the committed transaction.
+    pub fn with_txn_info<F, R, E>(&self, mut f: F) -> Result<(R, TxnInfo), E>
     where
         E: From<Error>,
         F: FnMut(TxnId) -> Result<R, E>,
@@ -50,8 +146,8 @@ where
         let txn_id = self.begin_txn();
         match f(txn_id) {
             Ok(result) => {
-                self.commit_txn(txn_id)?;
-                Ok(result)
+                let info = self.commit_txn(txn_id)?;
+                Ok((result, info))
             }
             Err(err) => {
                 self.abort_txn(txn_id)?;
@@ -60,6 +156,40 @@ where
         }
     }
 
+    /// Replace the value of `key` with `f` applied to its current value,
+    /// each attempt in a new transaction.  Attempts that fail because of a
+    /// conflict are retried, up to `max_attempts` in total, yielding the
+    /// thread between attempts so the conflicting transaction can finish.
+    /// At least one attempt is made, even if `max_attempts` is 0.
+    /// Returns the value written by the attempt that committed.
+    pub fn read_modify_write<V, F>(
+        &self,
+        keyspace_id: S,
+        key: &K,
+        max_attempts: usize,
+        mut f: F,
+    ) -> Result<V, Error>
+    where
+        V: Encode + Decode,
+        F: FnMut(Option<V>) -> V,
+    {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let result: Result<V, Error> = self.with_txn(|txn_id| {
+                let val = f(self.get(txn_id, keyspace_id, key)?);
+                self.set(txn_id, keyspace_id, key, &val)?;
+                Ok(val)
+            });
+            match result {
+                Err(err) if err.is_conflict() && attempts < max_attempts => {
+                    std::thread::yield_now()
+                }
+                result => return result,
+            }
+        }
+    }
+
     pub fn get<V>(&self, txn_id: TxnId, keyspace_id: S, key: &K) -> Result<Option<V>, Error>
     where
         V: Decode,
@@ -71,9 +201,241 @@ where
             .get(&keyspace_id)
             .ok_or(Error::UndefinedKeySpace)
             .and_then(|ks| ks.get(txn_id, key))
-            .and_then(|v| {
-                self.txn_manager.record_read(txn_id, keyspace_id, key);
-                Ok(v)
+            .inspect(|_| self.txn_manager.record_read(txn_id, keyspace_id, key))
+    }
+
+    /// Read the value of a key and take its write lock without writing it.
+    /// Other txns writing the key then fail immediately with
+    /// `Error::WriteWriteConflict`, rather than one of them failing validation
+    /// at commit, while concurrent readers are unaffected.  The lock is
+    /// released when the txn commits or aborts.  A key with no value is not
+    /// locked; a concurrent insert of it is caught at commit instead,
+    /// since the read was recorded.
+    pub fn get_for_update<V>(
+        &self,
+        txn_id: TxnId,
+        keyspace_id: S,
+        key: &K,
+    ) -> Result<Option<V>, Error>
+    where
+        V: Encode + Decode,
+    {
+        let val: Option<V> = self.get(txn_id, keyspace_id, key)?;
+        let result = self
+            .keyspace_map
+            .read()
+            .expect("Could not acquire read lock on keyspace map")
+            .get(&keyspace_id)
+            .ok_or(Error::UndefinedKeySpace)
+            .and_then(|ks| ks.lock(txn_id, key));
+        match result {
+            Ok(true) => self.txn_manager.record_lock(txn_id, keyspace_id, key),
+            Ok(false) => {}
+            Err(err) => return Err(self.report_write_conflict(txn_id, keyspace_id, key, err)),
+        }
+        Ok(val)
+    }
+
+    /// Read the value of a key as of timestamp `ts`, outside of any transaction.
+    /// Returns `Error::InvalidTimestamp` if a transaction that began before `ts`
+    /// is still active, since its changes could still become visible at `ts`,
+    /// and `Error::SnapshotTooOld` if the versions at `ts` may have been vacuumed.
+    pub fn get_as_of<V>(&self, ts: TxnId, keyspace_id: S, key: &K) -> Result<Option<V>, Error>
+    where
+        V: Decode,
+    {
+        if ts >= self.txn_manager.stable_ts() {
+            return Err(Error::InvalidTimestamp);
+        }
+        self.check_not_vacuumed(ts)?;
+        let result = self.read_as_of(ts, keyspace_id, key)?;
+
+        // A vacuum that started during the read could have unlinked the version.
+        self.check_not_vacuumed(ts)?;
+        Ok(result)
+    }
+
+    /// Pin the latest timestamp at which every transaction has finished,
+    /// and return a snapshot for reading at that timestamp.  Reads through
+    /// the snapshot do not begin transactions or record reads, and vacuum
+    /// keeps the versions they need until the snapshot is dropped.
+    pub fn snapshot(&self) -> Snapshot<'_, S, K> {
+        let mut pinned_snapshots = self
+            .pinned_snapshots
+            .lock()
+            .expect("Could not acquire lock on pinned snapshots");
+        let ts = self.txn_manager.stable_ts().checked_sub(1);
+        if let Some(ts) = ts {
+            *pinned_snapshots.entry(ts).or_insert(0) += 1;
+        }
+        Snapshot::new(self, ts)
+    }
+
+    pub(crate) fn unpin_snapshot(&self, ts: TxnId) {
+        let mut pinned_snapshots = self
+            .pinned_snapshots
+            .lock()
+            .expect("Could not acquire lock on pinned snapshots");
+        if let Some(count) = pinned_snapshots.get_mut(&ts) {
+            *count -= 1;
+            if *count == 0 {
+                pinned_snapshots.remove(&ts);
+            }
+        }
+    }
+
+    /// Read without checking the timestamp.  The caller must ensure that
+    /// `ts` is stable and that its versions have not been vacuumed.
+    pub(crate) fn read_as_of<V>(
+        &self,
+        ts: TxnId,
+        keyspace_id: S,
+        key: &K,
+    ) -> Result<Option<V>, Error>
+    where
+        V: Decode,
+    {
+        self.keyspace_map
+            .read()
+            .expect("Could not acquire read lock on keyspace map")
+            .get(&keyspace_id)
+            .ok_or(Error::UndefinedKeySpace)
+            .and_then(|ks| ks.get_as_of(ts, key))
+    }
+
+    // Oldest timestamp that an active or future transaction or an open
+    // snapshot can read at, or None if no transaction has begun.
+    fn read_horizon(&self, pinned_snapshots: &BTreeMap<TxnId, usize>) -> Option<TxnId> {
+        let stable_ts = self.txn_manager.stable_ts().checked_sub(1);
+        let oldest_snapshot_ts = pinned_snapshots.keys().next().copied();
+        match (stable_ts, oldest_snapshot_ts) {
+            (Some(stable_ts), Some(snapshot_ts)) => Some(stable_ts.min(snapshot_ts)),
+            (stable_ts, _) => stable_ts,
+        }
+    }
+
+    /// Turn a keyspace into a bounded cache of at most `max_keys` keys.
+    /// After each commit, committed keys over the limit are evicted in the
+    /// order given by `policy`, and reads of evicted keys return `None`.
+    /// Keys that an active transaction has read or written, or that an open
+    /// snapshot can see, are never evicted, so the keyspace may temporarily
+    /// exceed the limit.  Point-in-time reads at or before the last eviction
+    /// fail with `Error::SnapshotTooOld`; read through a snapshot instead.
+    pub fn set_eviction_policy(
+        &self,
+        keyspace_id: S,
+        policy: EvictionPolicy,
+        max_keys: usize,
+    ) -> Result<(), Error> {
+        self.keyspace_map
+            .read()
+            .expect("Could not acquire read lock on keyspace map")
+            .get(&keyspace_id)
+            .ok_or(Error::UndefinedKeySpace)
+            .map(|ks| ks.set_eviction_policy(policy, max_keys))?;
+        self.has_eviction_policy.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn evict_keys(&self) {
+        if !self.has_eviction_policy.load(Ordering::SeqCst) {
+            return;
+        }
+        // Hold the lock so that no snapshot is pinned while keys it could
+        // see are being evicted.
+        let pinned_snapshots = self
+            .pinned_snapshots
+            .lock()
+            .expect("Could not acquire lock on pinned snapshots");
+        let horizon = match self.txn_manager.stable_ts().checked_sub(1) {
+            None => return,
+            Some(horizon) => horizon,
+        };
+        let snapshot_ts = pinned_snapshots.keys().next_back().copied();
+        let mut num_evicted = 0;
+        for ks in self
+            .keyspace_map
+            .read()
+            .expect("Could not acquire read lock on keyspace map")
+            .values()
+        {
+            num_evicted += ks.evict(horizon, snapshot_ts);
+        }
+        if num_evicted > 0 {
+            // Point-in-time reads at or before the horizon could miss
+            // evicted keys, so fail them rather than return `None`.
+            self.gc_horizon.fetch_max(horizon + 1, Ordering::SeqCst);
+        }
+    }
+
+    pub(crate) fn check_keyspace_defined(&self, keyspace_id: S) -> Result<(), Error> {
+        if self
+            .keyspace_map
+            .read()
+            .expect("Could not acquire read lock on keyspace map")
+            .contains_key(&keyspace_id)
+        {
+            Ok(())
+        } else {
+            Err(Error::UndefinedKeySpace)
+        }
+    }
+
+    /// Release memory held by unreachable versions and spare capacity
+    /// in every keyspace.  Run `vacuum()` first to make old versions
+    /// unreachable.  Blocks all access to each keyspace while it is compacted.
+    pub fn shrink_to_fit(&self) {
+        for ks in self
+            .keyspace_map
+            .read()
+            .expect("Could not acquire read lock on keyspace map")
+            .values()
+        {
+            ks.shrink_to_fit();
+        }
+    }
+
+    /// Check every keyspace for broken key map and version chain invariants,
+    /// returning each problem found with its keyspace.  Nothing is repaired.
+    pub fn verify(&self) -> Vec<(S, Inconsistency)> {
+        let mut problems = Vec::new();
+        for (keyspace_id, ks) in self
+            .keyspace_map
+            .read()
+            .expect("Could not acquire read lock on keyspace map")
+            .iter()
+        {
+            problems.extend(ks.verify().into_iter().map(|p| (*keyspace_id, p)));
+        }
+        problems
+    }
+
+    /// Unlink versions of keys in a keyspace that no active or future
+    /// transaction or open snapshot can see.  Point-in-time reads before
+    /// the oldest of these will fail with `Error::SnapshotTooOld` afterwards.
+    /// Call `shrink_to_fit()` to free the memory of unlinked versions.
+    pub fn vacuum(&self, keyspace_id: S) -> Result<VacuumStats, Error> {
+        let horizon = {
+            // Hold the lock so that no snapshot is pinned below the horizon.
+            let pinned_snapshots = self
+                .pinned_snapshots
+                .lock()
+                .expect("Could not acquire lock on pinned snapshots");
+            let horizon = self.read_horizon(&pinned_snapshots);
+            if let Some(horizon) = horizon {
+                self.gc_horizon.fetch_max(horizon, Ordering::SeqCst);
+            }
+            horizon
+        };
+
+        self.keyspace_map
+            .read()
+            .expect("Could not acquire read lock on keyspace map")
+            .get(&keyspace_id)
+            .ok_or(Error::UndefinedKeySpace)
+            .map(|ks| match horizon {
+                Some(horizon) => ks.vacuum(horizon),
+                None => VacuumStats::default(),
             })
     }
 
@@ -82,16 +444,37 @@ where
         V: Encode,
     {
         self.check_is_valid_txn(txn_id)?;
+        let result = {
+            let keyspace_map = self
+                .keyspace_map
+                .read()
+                .expect("Could not acquire read lock on keyspace map");
+            let ks = keyspace_map
+                .get(&keyspace_id)
+                .ok_or(Error::UndefinedKeySpace)?;
+
+            if ks.can_skip_write(txn_id, key, val) {
+                // The txn depends on the value staying the same, which commit
+                // validation checks for reads, so record a read instead of a write.
+                self.txn_manager.record_read(txn_id, keyspace_id, key);
+                return Ok(());
+            }
+            ks.set(txn_id, key, val)
+                .map(|_| self.txn_manager.record_write(txn_id, keyspace_id, key))
+        };
+        result.map_err(|err| self.report_write_conflict(txn_id, keyspace_id, key, err))
+    }
+
+    /// When enabled, writing a value that equals the value the txn already
+    /// sees is a no-op validated like a read.  Concurrent txns writing the
+    /// same value to a key then no longer conflict with each other.
+    pub fn set_skip_identical_writes(&self, keyspace_id: S, enabled: bool) -> Result<(), Error> {
         self.keyspace_map
             .read()
             .expect("Could not acquire read lock on keyspace map")
             .get(&keyspace_id)
             .ok_or(Error::UndefinedKeySpace)
-            .and_then(|ks| ks.set(txn_id, key, val))
-            .and_then(|_| {
-                self.txn_manager.record_write(txn_id, keyspace_id, key);
-                Ok(())
-            })
+            .map(|ks| ks.set_skip_identical_writes(enabled))
     }
 
     pub fn delete(&self, txn_id: TxnId, keyspace_id: S, key: &K) -> Result<(), Error> {
@@ -102,28 +485,126 @@ where
             .get(&keyspace_id)
             .ok_or(Error::UndefinedKeySpace)
             .and_then(|ks| ks.delete(txn_id, key))
-            .and_then(|_| {
-                self.txn_manager.record_write(txn_id, keyspace_id, key);
-                Ok(())
-            })
+            .map(|_| self.txn_manager.record_write(txn_id, keyspace_id, key))
+            .map_err(|err| self.report_write_conflict(txn_id, keyspace_id, key, err))
     }
 
-    fn begin_txn(&self) -> TxnId {
-        self.txn_manager.begin_txn()
+    /// Begin a transaction that the caller is responsible for
+    /// committing or aborting.  Prefer `with_txn()` unless the
+    /// transaction must outlive a single closure.
+    pub fn begin_txn(&self) -> TxnId {
+        self.metrics.record_begin();
+        self.txn_manager.begin_txn(None)
     }
 
-    fn commit_txn(&self, txn_id: TxnId) -> Result<(), Error> {
-        self.txn_manager.commit_txn(
+    /// Begin a transaction tagged with a label that identifies the workload
+    /// in conflict events, such as "checkout".
+    pub fn begin_txn_labeled(&self, label: &str) -> TxnId {
+        self.metrics.record_begin();
+        self.txn_manager.begin_txn(Some(label.to_string()))
+    }
+
+    /// Label of an active or recently committed transaction.
+    pub fn txn_label(&self, txn_id: TxnId) -> Option<String> {
+        self.txn_manager.label(txn_id)
+    }
+
+    /// Install a callback to be invoked whenever a write or commit fails
+    /// because of a conflict, replacing any previous callback.
+    pub fn set_conflict_hook(&self, hook: ConflictHook<S, K>) {
+        *self
+            .conflict_hook
+            .write()
+            .expect("Could not acquire write lock on conflict hook") = Some(hook);
+    }
+
+    /// Commit a transaction started with `begin_txn()`, returning its timestamps.
+    /// If validation fails, the transaction is aborted and is no longer valid.
+    pub fn commit_txn(&self, txn_id: TxnId) -> Result<TxnInfo, Error> {
+        let mut conflict = None;
+        let result = self.txn_manager.commit_txn(
             txn_id,
             |keyspace_id, key_set| self.commit_keys(keyspace_id, key_set),
             |keyspace_id, key_set| self.abort_keys(keyspace_id, key_set),
-        )
+            |keyspace_id, key_set| self.unlock_keys(txn_id, keyspace_id, key_set),
+            |event| conflict = Some(event),
+        );
+
+        // Run the hook after the txn manager has released its locks.
+        if let Some(event) = conflict {
+            self.run_conflict_hook(&event);
+        }
+        self.metrics.record_commit(&result);
+        if result.is_ok() {
+            self.evict_keys();
+        }
+        result
+    }
+
+    /// Abort a transaction started with `begin_txn()`, discarding its changes.
+    pub fn abort_txn(&self, txn_id: TxnId) -> Result<(), Error> {
+        let result = self.txn_manager.abort_txn(
+            txn_id,
+            |keyspace_id, key_set| self.abort_keys(keyspace_id, key_set),
+            |keyspace_id, key_set| self.unlock_keys(txn_id, keyspace_id, key_set),
+        );
+        self.metrics.record_abort(&result);
+        result
+    }
+
+    /// Counters of transactions and conflicts since the store was created.
+    pub fn metrics(&self) -> StoreMetrics {
+        self.metrics.snapshot()
+    }
+
+    fn report_write_conflict(&self, txn_id: TxnId, keyspace_id: S, key: &K, err: Error) -> Error {
+        self.metrics.record_error(&err);
+        let is_conflict = matches!(err, Error::WriteWriteConflict | Error::ReadWriteConflict);
+        let has_hook = self
+            .conflict_hook
+            .read()
+            .expect("Could not acquire read lock on conflict hook")
+            .is_some();
+        if !is_conflict || !has_hook {
+            return err;
+        }
+
+        let other_txn_id = self
+            .keyspace_map
+            .read()
+            .expect("Could not acquire read lock on keyspace map")
+            .get(&keyspace_id)
+            .and_then(|ks| ks.conflicting_txn(key, &err));
+        let event = ConflictEvent {
+            error: err,
+            keyspace_id,
+            key: key.clone(),
+            txn_id,
+            txn_label: self.txn_manager.label(txn_id),
+            other_txn_id,
+            other_txn_label: other_txn_id.and_then(|id| self.txn_manager.label(id)),
+        };
+        self.run_conflict_hook(&event);
+        event.error
+    }
+
+    fn run_conflict_hook(&self, event: &ConflictEvent<S, K>) {
+        if let Some(hook) = self
+            .conflict_hook
+            .read()
+            .expect("Could not acquire read lock on conflict hook")
+            .as_ref()
+        {
+            hook(event);
+        }
     }
 
-    fn abort_txn(&self, txn_id: TxnId) -> Result<(), Error> {
-        self.txn_manager.abort_txn(txn_id, |keyspace_id, key_set| {
-            self.abort_keys(keyspace_id, key_set)
-        })
+    fn check_not_vacuumed(&self, ts: TxnId) -> Result<(), Error> {
+        if ts < self.gc_horizon.load(Ordering::SeqCst) {
+            Err(Error::SnapshotTooOld)
+        } else {
+            Ok(())
+        }
     }
 
     fn check_is_valid_txn(&self, txn_id: TxnId) -> Result<(), Error> {
@@ -151,11 +632,21 @@ where
             .expect("Invalid key space ID")
             .abort_keys(key_set)
     }
+
+    fn unlock_keys(&self, txn_id: TxnId, keyspace_id: S, key_set: &HashSet<K>) {
+        self.keyspace_map
+            .read()
+            .expect("Could not acquire read lock on keyspace map")
+            .get(&keyspace_id)
+            .expect("Invalid key space ID")
+            .unlock_keys(txn_id, key_set)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
 
     #[derive(Eq, PartialEq, Clone, Copy, Hash)]
     pub struct TestKeySpace {}
@@ -227,7 +718,7 @@ mod tests {
                     assert_eq!(result, expect);
                 }
                 Step::CommitTxn { txn_id, expect } => {
-                    let result = store.commit_txn(txn_id);
+                    let result = store.commit_txn(txn_id).map(|_| ());
                     assert_eq!(result, expect);
                 }
                 Step::AbortTxn { txn_id, expect } => {
@@ -290,14 +781,14 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 2 },
+            Step::BeginTxn { expect: 1 },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 4 },
+            Step::BeginTxn { expect: 2 },
             Step::CommitTxn {
-                txn_id: 4,
+                txn_id: 2,
                 expect: Ok(()),
             },
         ])
@@ -450,14 +941,14 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 2 },
+            Step::BeginTxn { expect: 1 },
             Step::Get {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 expect: Ok(Some("bar".to_string())),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
         ])
@@ -483,14 +974,14 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 2 },
+            Step::BeginTxn { expect: 1 },
             Step::Get {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 expect: Ok(Some("updated".to_string())),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
         ])
@@ -553,24 +1044,24 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 2 },
+            Step::BeginTxn { expect: 1 },
             Step::Del {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 expect: Ok(()),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 4 },
+            Step::BeginTxn { expect: 2 },
             Step::Get {
-                txn_id: 4,
+                txn_id: 2,
                 key: "foo",
                 expect: Ok(None),
             },
             Step::CommitTxn {
-                txn_id: 4,
+                txn_id: 2,
                 expect: Ok(()),
             },
         ])
@@ -595,14 +1086,14 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 2 },
+            Step::BeginTxn { expect: 1 },
             Step::Get {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 expect: Ok(None),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
         ]);
@@ -622,25 +1113,25 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 2 },
+            Step::BeginTxn { expect: 1 },
             Step::Set {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 val: "updated",
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 3 },
+            Step::BeginTxn { expect: 2 },
             Step::Get {
-                txn_id: 3,
+                txn_id: 2,
                 key: "foo",
                 expect: Ok(Some("bar".to_string())),
             },
             Step::CommitTxn {
-                txn_id: 3,
+                txn_id: 2,
                 expect: Ok(()),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
         ])
@@ -660,24 +1151,24 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 2 },
+            Step::BeginTxn { expect: 1 },
             Step::Del {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 3 },
+            Step::BeginTxn { expect: 2 },
             Step::Get {
-                txn_id: 3,
+                txn_id: 2,
                 key: "foo",
                 expect: Ok(Some("bar".to_string())),
             },
             Step::CommitTxn {
-                txn_id: 3,
+                txn_id: 2,
                 expect: Ok(()),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
         ])
@@ -697,25 +1188,25 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
+            Step::BeginTxn { expect: 1 },
             Step::BeginTxn { expect: 2 },
-            Step::BeginTxn { expect: 3 },
             Step::Get {
-                txn_id: 3,
+                txn_id: 2,
                 key: "foo",
                 expect: Ok(Some("bar".to_string())),
             },
             Step::Set {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 val: "updated",
                 expect: Err(Error::ReadWriteConflict),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
             Step::CommitTxn {
-                txn_id: 3,
+                txn_id: 2,
                 expect: Ok(()),
             },
         ])
@@ -735,24 +1226,24 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
+            Step::BeginTxn { expect: 1 },
             Step::BeginTxn { expect: 2 },
-            Step::BeginTxn { expect: 3 },
             Step::Get {
-                txn_id: 3,
+                txn_id: 2,
                 key: "foo",
                 expect: Ok(Some("bar".to_string())),
             },
             Step::Del {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 expect: Err(Error::ReadWriteConflict),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
             Step::CommitTxn {
-                txn_id: 3,
+                txn_id: 2,
                 expect: Ok(()),
             },
         ])
@@ -772,26 +1263,26 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 2 },
+            Step::BeginTxn { expect: 1 },
             Step::Set {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 val: "updated",
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 3 },
+            Step::BeginTxn { expect: 2 },
             Step::Set {
-                txn_id: 3,
+                txn_id: 2,
                 key: "foo",
                 val: "conflict",
                 expect: Err(Error::WriteWriteConflict),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
             Step::CommitTxn {
-                txn_id: 3,
+                txn_id: 2,
                 expect: Ok(()),
             },
         ])
@@ -811,25 +1302,25 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 2 },
+            Step::BeginTxn { expect: 1 },
             Step::Set {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 val: "updated",
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 3 },
+            Step::BeginTxn { expect: 2 },
             Step::Del {
-                txn_id: 3,
+                txn_id: 2,
                 key: "foo",
                 expect: Err(Error::WriteWriteConflict),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
             Step::CommitTxn {
-                txn_id: 3,
+                txn_id: 2,
                 expect: Ok(()),
             },
         ])
@@ -873,19 +1364,19 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 2 },
+            Step::BeginTxn { expect: 1 },
             Step::Get {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 expect: Ok(Some("bing".to_string())),
             },
             Step::Get {
-                txn_id: 2,
+                txn_id: 1,
                 key: "baa",
                 expect: Ok(Some("biz".to_string())),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
         ])
@@ -959,25 +1450,25 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
+            Step::BeginTxn { expect: 1 },
             Step::BeginTxn { expect: 2 },
-            Step::BeginTxn { expect: 3 },
             Step::Set {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 val: "phantom",
                 expect: Ok(()),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
             Step::Get {
-                txn_id: 3,
+                txn_id: 2,
                 key: "foo",
                 expect: Ok(Some("phantom".to_string())),
             },
             Step::CommitTxn {
-                txn_id: 3,
+                txn_id: 2,
                 expect: Err(Error::PhantomDetected),
             },
         ])
@@ -997,24 +1488,24 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
+            Step::BeginTxn { expect: 1 },
             Step::BeginTxn { expect: 2 },
-            Step::BeginTxn { expect: 3 },
             Step::Del {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 expect: Ok(()),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
             Step::Get {
-                txn_id: 3,
+                txn_id: 2,
                 key: "foo",
                 expect: Ok(None),
             },
             Step::CommitTxn {
-                txn_id: 3,
+                txn_id: 2,
                 expect: Err(Error::PhantomDetected),
             },
         ])
@@ -1082,14 +1573,14 @@ mod tests {
                 txn_id: 1,
                 expect: Err(Error::PhantomDetected),
             },
-            Step::BeginTxn { expect: 3 },
+            Step::BeginTxn { expect: 2 },
             Step::Get {
-                txn_id: 3,
+                txn_id: 2,
                 key: "revert",
                 expect: Ok(None),
             },
             Step::CommitTxn {
-                txn_id: 3,
+                txn_id: 2,
                 expect: Ok(()),
             },
         ])
@@ -1109,41 +1600,41 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
+            Step::BeginTxn { expect: 1 },
             Step::BeginTxn { expect: 2 },
-            Step::BeginTxn { expect: 3 },
             Step::Set {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 val: "phantom",
                 expect: Ok(()),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
             Step::Get {
-                txn_id: 3,
+                txn_id: 2,
                 key: "foo",
                 expect: Ok(Some("phantom".to_string())),
             },
             Step::Set {
-                txn_id: 3,
+                txn_id: 2,
                 key: "foo",
                 val: "revert",
                 expect: Ok(()),
             },
             Step::CommitTxn {
-                txn_id: 3,
+                txn_id: 2,
                 expect: Err(Error::PhantomDetected),
             },
-            Step::BeginTxn { expect: 5 },
+            Step::BeginTxn { expect: 3 },
             Step::Get {
-                txn_id: 5,
+                txn_id: 3,
                 key: "foo",
                 expect: Ok(Some("phantom".to_string())),
             },
             Step::CommitTxn {
-                txn_id: 5,
+                txn_id: 3,
                 expect: Ok(()),
             },
         ])
@@ -1163,45 +1654,45 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
+            Step::BeginTxn { expect: 1 },
             Step::BeginTxn { expect: 2 },
-            Step::BeginTxn { expect: 3 },
             Step::Del {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 expect: Ok(()),
             },
             Step::Set {
-                txn_id: 2,
+                txn_id: 1,
                 key: "bar",
                 val: "revert",
                 expect: Ok(()),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
             Step::Get {
-                txn_id: 3,
+                txn_id: 2,
                 key: "foo",
                 expect: Ok(None),
             },
             Step::Del {
-                txn_id: 3,
+                txn_id: 2,
                 key: "bar",
                 expect: Ok(()),
             },
             Step::CommitTxn {
-                txn_id: 3,
+                txn_id: 2,
                 expect: Err(Error::PhantomDetected),
             },
-            Step::BeginTxn { expect: 5 },
+            Step::BeginTxn { expect: 3 },
             Step::Get {
-                txn_id: 5,
+                txn_id: 3,
                 key: "bar",
                 expect: Ok(Some("revert".to_string())),
             },
             Step::CommitTxn {
-                txn_id: 5,
+                txn_id: 3,
                 expect: Ok(()),
             },
         ])
@@ -1248,25 +1739,25 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 2 },
+            Step::BeginTxn { expect: 1 },
             Step::Set {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 val: "updated",
                 expect: Ok(()),
             },
             Step::AbortTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 3 },
+            Step::BeginTxn { expect: 2 },
             Step::Get {
-                txn_id: 3,
+                txn_id: 2,
                 key: "foo",
                 expect: Ok(Some("bar".to_string())),
             },
             Step::CommitTxn {
-                txn_id: 3,
+                txn_id: 2,
                 expect: Ok(()),
             },
         ])
@@ -1286,24 +1777,24 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 2 },
+            Step::BeginTxn { expect: 1 },
             Step::Del {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 expect: Ok(()),
             },
             Step::AbortTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 3 },
+            Step::BeginTxn { expect: 2 },
             Step::Get {
-                txn_id: 3,
+                txn_id: 2,
                 key: "foo",
                 expect: Ok(Some("bar".to_string())),
             },
             Step::CommitTxn {
-                txn_id: 3,
+                txn_id: 2,
                 expect: Ok(()),
             },
         ])
@@ -1347,19 +1838,19 @@ mod tests {
                 txn_id: 0,
                 expect: Ok(()),
             },
-            Step::BeginTxn { expect: 2 },
+            Step::BeginTxn { expect: 1 },
             Step::Get {
-                txn_id: 2,
+                txn_id: 1,
                 key: "foo",
                 expect: Ok(Some("bing".to_string())),
             },
             Step::Get {
-                txn_id: 2,
+                txn_id: 1,
                 key: "baa",
                 expect: Ok(Some("biz".to_string())),
             },
             Step::CommitTxn {
-                txn_id: 2,
+                txn_id: 1,
                 expect: Ok(()),
             },
         ])
@@ -1398,4 +1889,788 @@ mod tests {
             store.with_txn(|txn_id| store.get(txn_id, TestKeySpace {}, &key));
         assert_eq!(r2, Ok(None));
     }
+
+    #[test]
+    fn test_get_as_of() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+
+        let key = "foo";
+        for val in ["bar", "baz"].iter() {
+            let result: Result<(), Error> =
+                store.with_txn(|txn_id| store.set(txn_id, TestKeySpace {}, &key, val));
+            assert_eq!(result, Ok(()));
+        }
+
+        let get_as_of = |ts| store.get_as_of::<String>(ts, TestKeySpace {}, &key);
+        assert_eq!(get_as_of(0), Ok(Some("bar".to_string())));
+        assert_eq!(get_as_of(1), Ok(Some("baz".to_string())));
+        assert_eq!(get_as_of(2), Err(Error::InvalidTimestamp));
+    }
+
+    #[test]
+    fn test_get_as_of_active_txn() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+
+        let txn_id = store.begin_txn();
+        assert_eq!(
+            store.get_as_of::<String>(txn_id, TestKeySpace {}, &"foo"),
+            Err(Error::InvalidTimestamp)
+        );
+        store.abort_txn(txn_id).expect("Could not abort txn");
+        assert_eq!(
+            store.get_as_of::<String>(txn_id, TestKeySpace {}, &"foo"),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_keyspace_stats() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+
+        let stats = store
+            .keyspace_stats(TestKeySpace {})
+            .expect("Could not get stats");
+        assert_eq!(
+            stats,
+            KeySpaceStats {
+                num_keys: 0,
+                num_versions: 0,
+                num_value_bytes: 0,
+                num_allocated_bytes: 0,
+            }
+        );
+
+        for val in ["bar", "bazz"].iter() {
+            let result: Result<(), Error> =
+                store.with_txn(|txn_id| store.set(txn_id, TestKeySpace {}, &"foo", val));
+            assert_eq!(result, Ok(()));
+        }
+
+        let stats = store
+            .keyspace_stats(TestKeySpace {})
+            .expect("Could not get stats");
+        assert_eq!(
+            stats,
+            KeySpaceStats {
+                num_keys: 1,
+                num_versions: 2,
+                num_value_bytes: 8 + 3 + 8 + 4,
+                num_allocated_bytes: stats.num_allocated_bytes,
+            }
+        );
+    }
+
+    #[test]
+    fn test_vacuum() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+
+        for val in ["a", "bb", "ccc"].iter() {
+            let result: Result<(), Error> =
+                store.with_txn(|txn_id| store.set(txn_id, TestKeySpace {}, &"foo", val));
+            assert_eq!(result, Ok(()));
+        }
+        let result: Result<(), Error> = store.with_txn(|txn_id| {
+            store.set(txn_id, TestKeySpace {}, &"bar", &"x")?;
+            store.set(txn_id, TestKeySpace {}, &"baz", &"y")
+        });
+        assert_eq!(result, Ok(()));
+        let result: Result<(), Error> =
+            store.with_txn(|txn_id| store.delete(txn_id, TestKeySpace {}, &"baz"));
+        assert_eq!(result, Ok(()));
+
+        let stats = store.vacuum(TestKeySpace {}).expect("Could not vacuum");
+        assert_eq!(
+            stats,
+            VacuumStats {
+                removed_keys: 1,
+                unlinked_versions: 4,
+                unlinked_bytes: (8 + 1) + (8 + 2) + (8 + 1),
+            }
+        );
+
+        // vacuuming again finds nothing more to reclaim
+        let stats = store.vacuum(TestKeySpace {}).expect("Could not vacuum");
+        assert_eq!(stats, VacuumStats::default());
+
+        let result: Result<(Option<String>, Option<String>), Error> = store.with_txn(|txn_id| {
+            let foo = store.get(txn_id, TestKeySpace {}, &"foo")?;
+            let baz = store.get(txn_id, TestKeySpace {}, &"baz")?;
+            Ok((foo, baz))
+        });
+        assert_eq!(result, Ok((Some("ccc".to_string()), None)));
+        assert_eq!(
+            store.get_as_of::<String>(0, TestKeySpace {}, &"foo"),
+            Err(Error::SnapshotTooOld)
+        );
+    }
+
+    #[test]
+    fn test_vacuum_keeps_versions_visible_to_active_txn() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+
+        let result: Result<(), Error> =
+            store.with_txn(|txn_id| store.set(txn_id, TestKeySpace {}, &"foo", &"old"));
+        assert_eq!(result, Ok(()));
+
+        let reader = store.begin_txn();
+        let result: Result<(), Error> =
+            store.with_txn(|txn_id| store.set(txn_id, TestKeySpace {}, &"foo", &"new"));
+        assert_eq!(result, Ok(()));
+        let result: Result<(), Error> =
+            store.with_txn(|txn_id| store.delete(txn_id, TestKeySpace {}, &"foo"));
+        assert_eq!(result, Ok(()));
+
+        let stats = store.vacuum(TestKeySpace {}).expect("Could not vacuum");
+        assert_eq!(stats, VacuumStats::default());
+        assert_eq!(
+            store.get(reader, TestKeySpace {}, &"foo"),
+            Ok(Some("old".to_string()))
+        );
+        store.abort_txn(reader).expect("Could not abort txn");
+
+        let stats = store.vacuum(TestKeySpace {}).expect("Could not vacuum");
+        assert_eq!(stats.removed_keys, 1);
+        assert_eq!(stats.unlinked_versions, 3);
+    }
+
+    #[test]
+    fn test_concurrent_readers_block_older_writer() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        let result: Result<(), Error> =
+            store.with_txn(|txn_id| store.set(txn_id, TestKeySpace {}, &"foo", &"bar"));
+        assert_eq!(result, Ok(()));
+
+        let writer = store.begin_txn();
+        let readers: Vec<TxnId> = (0..8).map(|_| store.begin_txn()).collect();
+        std::thread::scope(|scope| {
+            for &reader in readers.iter() {
+                let store = &store;
+                scope.spawn(move || {
+                    for _ in 0..100 {
+                        let result = store.get(reader, TestKeySpace {}, &"foo");
+                        assert_eq!(result, Ok(Some("bar".to_string())));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(
+            store.set(writer, TestKeySpace {}, &"foo", &"baz"),
+            Err(Error::ReadWriteConflict)
+        );
+    }
+
+    #[test]
+    fn test_commit_txn_info() {
+        let store: Store<TestKeySpace, &str> = Store::new();
+        store.define_keyspace(TestKeySpace {});
+
+        let t0 = store.begin_txn();
+        let t1 = store.begin_txn();
+        assert_eq!((t0, t1), (0, 1));
+        assert_eq!(
+            store.commit_txn(t0),
+            Ok(TxnInfo {
+                begin_ts: 0,
+                commit_ts: 2
+            })
+        );
+
+        // commits do not consume transaction IDs
+        let t2 = store.begin_txn();
+        assert_eq!(t2, 2);
+        assert_eq!(
+            store.commit_txn(t1),
+            Ok(TxnInfo {
+                begin_ts: 1,
+                commit_ts: 3
+            })
+        );
+        // commit timestamps are unique, so this commit advances the clock
+        assert_eq!(
+            store.commit_txn(t2),
+            Ok(TxnInfo {
+                begin_ts: 2,
+                commit_ts: 4
+            })
+        );
+        assert_eq!(store.begin_txn(), 4);
+    }
+
+    #[test]
+    fn test_txn_after_commit_reads_without_phantom() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+
+        let writer = store.begin_txn();
+        store
+            .set(writer, TestKeySpace {}, &"foo", &"bar")
+            .expect("Could not set key");
+        let info = store.commit_txn(writer).expect("Could not commit txn");
+
+        // begins at the writer's commit timestamp, so the write is not concurrent
+        let reader = store.begin_txn();
+        assert_eq!(reader, info.commit_ts);
+        assert_eq!(
+            store.get(reader, TestKeySpace {}, &"foo"),
+            Ok(Some("bar".to_string()))
+        );
+        assert!(store.commit_txn(reader).is_ok());
+    }
+
+    #[test]
+    fn test_skip_identical_writes() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        let result: Result<(), Error> =
+            store.with_txn(|txn_id| store.set(txn_id, TestKeySpace {}, &"flag", &"on"));
+        assert_eq!(result, Ok(()));
+
+        // without the option, concurrent identical writes conflict
+        let t1 = store.begin_txn();
+        let t2 = store.begin_txn();
+        assert_eq!(store.set(t1, TestKeySpace {}, &"flag", &"on"), Ok(()));
+        assert_eq!(
+            store.set(t2, TestKeySpace {}, &"flag", &"on"),
+            Err(Error::WriteWriteConflict)
+        );
+        store.abort_txn(t1).expect("Could not abort txn");
+        store.abort_txn(t2).expect("Could not abort txn");
+
+        store
+            .set_skip_identical_writes(TestKeySpace {}, true)
+            .expect("Could not set option");
+        let t1 = store.begin_txn();
+        let t2 = store.begin_txn();
+        assert_eq!(store.set(t1, TestKeySpace {}, &"flag", &"on"), Ok(()));
+        assert_eq!(store.set(t2, TestKeySpace {}, &"flag", &"on"), Ok(()));
+        assert!(store.commit_txn(t1).is_ok());
+        assert!(store.commit_txn(t2).is_ok());
+        assert_eq!(
+            store
+                .keyspace_stats(TestKeySpace {})
+                .map(|s| s.num_versions),
+            Ok(2)
+        );
+    }
+
+    #[test]
+    fn test_skip_identical_writes_detects_changed_value() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        store
+            .set_skip_identical_writes(TestKeySpace {}, true)
+            .expect("Could not set option");
+        let result: Result<(), Error> =
+            store.with_txn(|txn_id| store.set(txn_id, TestKeySpace {}, &"flag", &"on"));
+        assert_eq!(result, Ok(()));
+
+        let t1 = store.begin_txn();
+        let t2 = store.begin_txn();
+        assert_eq!(store.set(t1, TestKeySpace {}, &"flag", &"on"), Ok(()));
+        assert_eq!(store.set(t2, TestKeySpace {}, &"flag", &"off"), Ok(()));
+        assert!(store.commit_txn(t2).is_ok());
+
+        // t1 skipped its write on the assumption that the flag stayed "on"
+        assert_eq!(store.commit_txn(t1), Err(Error::PhantomDetected));
+    }
+
+    type ConflictSummary = (
+        Error,
+        &'static str,
+        Option<String>,
+        Option<TxnId>,
+        Option<String>,
+    );
+
+    fn record_conflicts(
+        store: &Store<TestKeySpace, &'static str>,
+    ) -> Arc<Mutex<Vec<ConflictSummary>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let hook_events = Arc::clone(&events);
+        store.set_conflict_hook(Box::new(
+            move |event: &ConflictEvent<TestKeySpace, &'static str>| {
+                hook_events.lock().unwrap().push((
+                    match event.error {
+                        Error::WriteWriteConflict => Error::WriteWriteConflict,
+                        Error::ReadWriteConflict => Error::ReadWriteConflict,
+                        _ => Error::PhantomDetected,
+                    },
+                    event.key,
+                    event.txn_label.clone(),
+                    event.other_txn_id,
+                    event.other_txn_label.clone(),
+                ))
+            },
+        ));
+        events
+    }
+
+    #[test]
+    fn test_conflict_hook_write_conflicts() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        let events = record_conflicts(&store);
+        let result: Result<(), Error> =
+            store.with_txn(|txn_id| store.set(txn_id, TestKeySpace {}, &"stock", &"0"));
+        assert_eq!(result, Ok(()));
+
+        let t1 = store.begin_txn_labeled("checkout");
+        let t2 = store.begin_txn_labeled("restock");
+        assert_eq!(store.set(t1, TestKeySpace {}, &"stock", &"1"), Ok(()));
+        assert_eq!(
+            store.set(t2, TestKeySpace {}, &"stock", &"2"),
+            Err(Error::WriteWriteConflict)
+        );
+        store.abort_txn(t1).expect("Could not abort txn");
+        store.abort_txn(t2).expect("Could not abort txn");
+
+        let t3 = store.begin_txn();
+        let t4 = store.begin_txn_labeled("report");
+        assert_eq!(
+            store.get(t4, TestKeySpace {}, &"stock"),
+            Ok(Some("0".to_string()))
+        );
+        assert_eq!(
+            store.delete(t3, TestKeySpace {}, &"stock"),
+            Err(Error::ReadWriteConflict)
+        );
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                (
+                    Error::WriteWriteConflict,
+                    "stock",
+                    Some("restock".to_string()),
+                    Some(t1),
+                    Some("checkout".to_string())
+                ),
+                (
+                    Error::ReadWriteConflict,
+                    "stock",
+                    None,
+                    Some(t4),
+                    Some("report".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conflict_hook_phantom() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        let events = record_conflicts(&store);
+
+        let reader = store.begin_txn_labeled("report");
+        let writer = store.begin_txn_labeled("checkout");
+        assert_eq!(
+            store.get::<String>(reader, TestKeySpace {}, &"stock"),
+            Ok(None)
+        );
+        assert_eq!(store.set(writer, TestKeySpace {}, &"stock", &"1"), Ok(()));
+        assert!(store.commit_txn(writer).is_ok());
+        assert_eq!(store.commit_txn(reader), Err(Error::PhantomDetected));
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![(
+                Error::PhantomDetected,
+                "stock",
+                Some("report".to_string()),
+                Some(writer),
+                Some("checkout".to_string())
+            )]
+        );
+    }
+
+    fn set_committed(store: &Store<TestKeySpace, &'static str>, key: &'static str, val: &str) {
+        let result: Result<(), Error> =
+            store.with_txn(|txn_id| store.set(txn_id, TestKeySpace {}, &key, &val));
+        assert_eq!(result, Ok(()));
+    }
+
+    fn get_committed(
+        store: &Store<TestKeySpace, &'static str>,
+        key: &'static str,
+    ) -> Option<String> {
+        store
+            .with_txn(|txn_id| store.get(txn_id, TestKeySpace {}, &key))
+            .expect("Could not get key")
+    }
+
+    #[test]
+    fn test_fifo_eviction() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        store
+            .set_eviction_policy(TestKeySpace {}, EvictionPolicy::Fifo, 2)
+            .expect("Could not set eviction policy");
+
+        set_committed(&store, "a", "1");
+        set_committed(&store, "b", "2");
+        assert_eq!(get_committed(&store, "a"), Some("1".to_string()));
+        set_committed(&store, "c", "3");
+
+        assert_eq!(get_committed(&store, "a"), None);
+        assert_eq!(get_committed(&store, "b"), Some("2".to_string()));
+        assert_eq!(get_committed(&store, "c"), Some("3".to_string()));
+        assert_eq!(
+            store.keyspace_stats(TestKeySpace {}).map(|s| s.num_keys),
+            Ok(2)
+        );
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        store
+            .set_eviction_policy(TestKeySpace {}, EvictionPolicy::Lru, 2)
+            .expect("Could not set eviction policy");
+
+        set_committed(&store, "a", "1");
+        set_committed(&store, "b", "2");
+        assert_eq!(get_committed(&store, "a"), Some("1".to_string()));
+        set_committed(&store, "c", "3");
+
+        assert_eq!(get_committed(&store, "b"), None);
+        assert_eq!(get_committed(&store, "a"), Some("1".to_string()));
+        assert_eq!(get_committed(&store, "c"), Some("3".to_string()));
+    }
+
+    #[test]
+    fn test_eviction_skips_keys_in_use() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        store
+            .set_eviction_policy(TestKeySpace {}, EvictionPolicy::Fifo, 1)
+            .expect("Could not set eviction policy");
+
+        set_committed(&store, "a", "1");
+        let reader = store.begin_txn();
+        assert_eq!(
+            store.get(reader, TestKeySpace {}, &"a"),
+            Ok(Some("1".to_string()))
+        );
+        set_committed(&store, "b", "2");
+
+        // "a" was read by an active txn, and "b" is newer than the txn,
+        // so neither can be evicted yet
+        assert_eq!(
+            store.keyspace_stats(TestKeySpace {}).map(|s| s.num_keys),
+            Ok(2)
+        );
+        assert_eq!(
+            store.get(reader, TestKeySpace {}, &"a"),
+            Ok(Some("1".to_string()))
+        );
+        store.commit_txn(reader).expect("Could not commit txn");
+        set_committed(&store, "c", "3");
+        assert_eq!(get_committed(&store, "c"), Some("3".to_string()));
+        assert_eq!(
+            store.keyspace_stats(TestKeySpace {}).map(|s| s.num_keys),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+
+        for val in ["a", "bb", "ccc"].iter() {
+            set_committed(&store, "foo", val);
+        }
+        set_committed(&store, "bar", "x");
+        set_committed(&store, "baz", "y");
+        let result: Result<(), Error> =
+            store.with_txn(|txn_id| store.delete(txn_id, TestKeySpace {}, &"baz"));
+        assert_eq!(result, Ok(()));
+        let txn_id = store.begin_txn();
+        store
+            .set(txn_id, TestKeySpace {}, &"qux", &"aborted")
+            .expect("Could not set key");
+        store.abort_txn(txn_id).expect("Could not abort txn");
+
+        store.vacuum(TestKeySpace {}).expect("Could not vacuum");
+        let before = store
+            .keyspace_stats(TestKeySpace {})
+            .expect("Could not get stats");
+        store.shrink_to_fit();
+        let after = store
+            .keyspace_stats(TestKeySpace {})
+            .expect("Could not get stats");
+
+        assert_eq!((before.num_keys, before.num_versions), (2, 7));
+        assert_eq!((after.num_keys, after.num_versions), (2, 2));
+        assert_eq!(after.num_value_bytes, (8 + 3) + (8 + 1));
+        assert!(after.num_allocated_bytes < before.num_allocated_bytes);
+
+        assert_eq!(get_committed(&store, "foo"), Some("ccc".to_string()));
+        assert_eq!(get_committed(&store, "bar"), Some("x".to_string()));
+        assert_eq!(get_committed(&store, "baz"), None);
+        set_committed(&store, "foo", "dddd");
+        assert_eq!(get_committed(&store, "foo"), Some("dddd".to_string()));
+    }
+
+    #[test]
+    fn test_with_txn_info() {
+        let store: Store<TestKeySpace, &str> = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        let reader = store.begin_txn();
+        let result = store.with_txn_info(|txn_id| {
+            store.set(txn_id, TestKeySpace {}, &"foo", &"bar")?;
+            Ok::<_, Error>(txn_id)
+        });
+        let (txn_id, info) = result.expect("Could not commit txn");
+        assert_eq!(txn_id, reader + 1);
+        assert_eq!(
+            info,
+            TxnInfo {
+                begin_ts: txn_id,
+                commit_ts: txn_id + 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_get_for_update_locks_key() {
+        let store: Store<TestKeySpace, &str> = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        set_committed(&store, "foo", "bar");
+
+        let locker = store.begin_txn();
+        let writer = store.begin_txn();
+        let val: Option<String> = store
+            .get_for_update(locker, TestKeySpace {}, &"foo")
+            .expect("Could not get for update");
+        assert_eq!(val, Some("bar".to_string()));
+        assert_eq!(
+            store.set(writer, TestKeySpace {}, &"foo", &"baz"),
+            Err(Error::WriteWriteConflict)
+        );
+        assert!(store.verify().is_empty());
+
+        // The locker can still write the key.
+        store
+            .set(locker, TestKeySpace {}, &"foo", &"qux")
+            .expect("Could not set key");
+        store.commit_txn(locker).expect("Could not commit txn");
+        assert_eq!(get_committed(&store, "foo"), Some("qux".to_string()));
+        assert!(store.verify().is_empty());
+    }
+
+    #[test]
+    fn test_get_for_update_does_not_write() {
+        let store: Store<TestKeySpace, &str> = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        set_committed(&store, "foo", "bar");
+        let stats = store
+            .keyspace_stats(TestKeySpace {})
+            .expect("Could not get stats");
+
+        let locker = store.begin_txn();
+        let reader = store.begin_txn();
+        let _: Option<String> = store
+            .get_for_update(locker, TestKeySpace {}, &"foo")
+            .expect("Could not get for update");
+        let val: Option<String> = store
+            .get(reader, TestKeySpace {}, &"foo")
+            .expect("Could not get key");
+        assert_eq!(val, Some("bar".to_string()));
+
+        // Absent keys are read but not locked, so no tombstone is written.
+        let val: Option<String> = store
+            .get_for_update(locker, TestKeySpace {}, &"new")
+            .expect("Could not get for update");
+        assert_eq!(val, None);
+        assert_eq!(store.keyspace_stats(TestKeySpace {}), Ok(stats));
+
+        // The locker wrote nothing, so the concurrent reader still commits.
+        store.commit_txn(locker).expect("Could not commit txn");
+        store.commit_txn(reader).expect("Could not commit txn");
+        assert_eq!(store.keyspace_stats(TestKeySpace {}), Ok(stats));
+    }
+
+    #[test]
+    fn test_get_for_update_releases_lock() {
+        let store: Store<TestKeySpace, &str> = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        set_committed(&store, "foo", "bar");
+
+        let locker = store.begin_txn();
+        let _: Option<String> = store
+            .get_for_update(locker, TestKeySpace {}, &"foo")
+            .expect("Could not get for update");
+        store.abort_txn(locker).expect("Could not abort txn");
+
+        let locker = store.begin_txn();
+        let _: Option<String> = store
+            .get_for_update(locker, TestKeySpace {}, &"foo")
+            .expect("Could not get for update");
+        store.commit_txn(locker).expect("Could not commit txn");
+
+        set_committed(&store, "foo", "baz");
+        assert_eq!(get_committed(&store, "foo"), Some("baz".to_string()));
+        assert!(store.verify().is_empty());
+    }
+
+    #[test]
+    fn test_read_modify_write() {
+        let store: Store<TestKeySpace, &str> = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        let result = store.read_modify_write(TestKeySpace {}, &"count", 3, |count: Option<u64>| {
+            count.unwrap_or(0) + 1
+        });
+        assert_eq!(result, Ok(1));
+        let result = store.read_modify_write(TestKeySpace {}, &"count", 3, |count: Option<u64>| {
+            count.unwrap_or(0) + 1
+        });
+        assert_eq!(result, Ok(2));
+
+        // zero attempts still tries once
+        let result = store.read_modify_write(TestKeySpace {}, &"count", 0, |count: Option<u64>| {
+            count.unwrap_or(0) + 1
+        });
+        assert_eq!(result, Ok(3));
+    }
+
+    #[test]
+    fn test_read_modify_write_retries_conflicts() {
+        let store: Store<TestKeySpace, &str> = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        let blocker = store.begin_txn();
+        store
+            .set(blocker, TestKeySpace {}, &"count", &10u64)
+            .expect("Could not set key");
+
+        // Every attempt conflicts with the blocker's uncommitted write.
+        let mut attempts = 0;
+        let result = store.read_modify_write(TestKeySpace {}, &"count", 3, |count: Option<u64>| {
+            attempts += 1;
+            count.unwrap_or(0) + 1
+        });
+        assert_eq!(result, Err(Error::WriteWriteConflict));
+        assert_eq!(attempts, 3);
+
+        store.commit_txn(blocker).expect("Could not commit txn");
+        let mut attempts = 0;
+        let result = store.read_modify_write(TestKeySpace {}, &"count", 3, |count: Option<u64>| {
+            attempts += 1;
+            count.unwrap_or(0) + 1
+        });
+        assert_eq!(result, Ok(11));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_verify_consistent_store() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        assert!(store.verify().is_empty());
+
+        for val in ["a", "b", "c"].iter() {
+            set_committed(&store, "foo", val);
+        }
+        set_committed(&store, "bar", "x");
+        let reader = store.begin_txn();
+        let result: Result<(), Error> =
+            store.with_txn(|txn_id| store.delete(txn_id, TestKeySpace {}, &"bar"));
+        assert_eq!(result, Ok(()));
+
+        let aborted = store.begin_txn();
+        store
+            .set(aborted, TestKeySpace {}, &"qux", &"aborted")
+            .expect("Could not set key");
+        store.abort_txn(aborted).expect("Could not abort txn");
+
+        let writer = store.begin_txn();
+        store
+            .set(writer, TestKeySpace {}, &"foo", &"uncommitted")
+            .expect("Could not set key");
+        assert!(store.verify().is_empty());
+
+        store.abort_txn(reader).expect("Could not abort txn");
+        store.vacuum(TestKeySpace {}).expect("Could not vacuum");
+        store.shrink_to_fit();
+        assert!(store.verify().is_empty());
+
+        store.commit_txn(writer).expect("Could not commit txn");
+        assert!(store.verify().is_empty());
+    }
+
+    #[test]
+    fn test_shrink_to_fit_keeps_visible_versions() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        set_committed(&store, "foo", "old");
+
+        let reader = store.begin_txn();
+        set_committed(&store, "foo", "new");
+        let writer = store.begin_txn();
+        store
+            .set(writer, TestKeySpace {}, &"foo", &"uncommitted")
+            .expect("Could not set key");
+        store.shrink_to_fit();
+
+        assert_eq!(
+            store.get(reader, TestKeySpace {}, &"foo"),
+            Ok(Some("old".to_string()))
+        );
+        assert_eq!(
+            store.get(writer, TestKeySpace {}, &"foo"),
+            Ok(Some("uncommitted".to_string()))
+        );
+        store.abort_txn(reader).expect("Could not abort txn");
+        store.commit_txn(writer).expect("Could not commit txn");
+        assert_eq!(
+            get_committed(&store, "foo"),
+            Some("uncommitted".to_string())
+        );
+    }
+
+    #[test]
+    fn test_metrics() {
+        let store = Store::new();
+        store.define_keyspace(TestKeySpace {});
+        set_committed(&store, "foo", "bar");
+
+        let t1 = store.begin_txn();
+        let t2 = store.begin_txn();
+        assert_eq!(store.set(t1, TestKeySpace {}, &"foo", &"1"), Ok(()));
+        assert_eq!(
+            store.set(t2, TestKeySpace {}, &"foo", &"2"),
+            Err(Error::WriteWriteConflict)
+        );
+        store.abort_txn(t2).expect("Could not abort txn");
+        assert_eq!(store.get::<String>(t1, TestKeySpace {}, &"baz"), Ok(None));
+
+        let t3 = store.begin_txn();
+        set_committed(&store, "baz", "qux");
+        assert!(store.commit_txn(t1).is_err());
+        store.abort_txn(t3).expect("Could not abort txn");
+
+        // committing a txn that is no longer active is not an abort
+        assert_eq!(store.commit_txn(t3), Err(Error::InvalidTxnId));
+
+        assert_eq!(
+            store.metrics(),
+            StoreMetrics {
+                txns_begun: 5,
+                txns_committed: 2,
+                txns_aborted: 3,
+                read_write_conflicts: 0,
+                write_write_conflicts: 1,
+                phantoms_detected: 1,
+            }
+        );
+    }
 }
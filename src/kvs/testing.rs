@@ -0,0 +1,358 @@
+//! Randomized checks that the store only commits serializable histories.
+//!
+//! A history interleaves the reads and writes of several transactions over a
+//! small set of keys.  Each transaction writes its own index as the value, so
+//! every read records which transaction wrote the version it observed.  The
+//! committed transactions are then checked for a cycle in the dependency graph
+//! (write-read, write-write and read-write edges), which would mean no serial
+//! order produces the same reads.
+
+use crate::kvs::keyspace::KeySpaceId;
+use crate::kvs::store::Store;
+use crate::kvs::txn::TxnId;
+use std::collections::HashMap;
+
+#[derive(Eq, PartialEq, Clone, Copy, Hash)]
+struct HistoryKeySpace {}
+impl KeySpaceId for HistoryKeySpace {}
+
+/// Shape of the randomly generated histories.
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryConfig {
+    pub num_txns: usize,
+    pub num_keys: u64,
+    pub ops_per_txn: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            num_txns: 4,
+            num_keys: 3,
+            ops_per_txn: 4,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Op {
+    /// Read `key` and observe the version written by transaction `writer`,
+    /// or the initial (absent) value if `writer` is `None`.
+    Read {
+        key: u64,
+        writer: Option<usize>,
+    },
+    Write {
+        key: u64,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TxnRecord {
+    pub ops: Vec<Op>,
+    pub committed: bool,
+}
+
+/// Transactions of a history, indexed by their position in `txns`.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    pub txns: Vec<TxnRecord>,
+
+    /// Indices of committed transactions in the order they committed.
+    pub commit_order: Vec<usize>,
+}
+
+/// Run a random interleaving of transactions against a new store.
+/// The same seed always produces the same history.
+/// Returns an empty history if `config` has no keys to operate on.
+pub fn run_random_history(seed: u64, config: HistoryConfig) -> History {
+    if config.num_keys == 0 {
+        return History::default();
+    }
+
+    let mut rng = Rng::new(seed);
+    let store: Store<HistoryKeySpace, u64> = Store::new();
+    store.define_keyspace(HistoryKeySpace {});
+
+    let mut history = History {
+        txns: vec![TxnRecord::default(); config.num_txns],
+        commit_order: Vec::new(),
+    };
+    let mut txn_ids: Vec<Option<TxnId>> = vec![None; config.num_txns];
+    let mut remaining: Vec<usize> = (0..config.num_txns).collect();
+
+    while !remaining.is_empty() {
+        let pos = rng.below(remaining.len() as u64) as usize;
+        let idx = remaining[pos];
+        let txn_id = *txn_ids[idx].get_or_insert_with(|| store.begin_txn());
+        let record = &mut history.txns[idx];
+
+        if record.ops.len() == config.ops_per_txn {
+            if store.commit_txn(txn_id).is_ok() {
+                record.committed = true;
+                history.commit_order.push(idx);
+            }
+            remaining.remove(pos);
+            continue;
+        }
+
+        let key = rng.below(config.num_keys);
+        let result = if rng.below(2) == 0 {
+            store
+                .get::<usize>(txn_id, HistoryKeySpace {}, &key)
+                .map(|writer| Op::Read { key, writer })
+        } else {
+            store
+                .set(txn_id, HistoryKeySpace {}, &key, &idx)
+                .map(|_| Op::Write { key })
+        };
+
+        match result {
+            Ok(op) => record.ops.push(op),
+            Err(_) => {
+                store.abort_txn(txn_id).expect("Could not abort txn");
+                remaining.remove(pos);
+            }
+        }
+    }
+
+    history
+}
+
+impl History {
+    /// Check that the committed transactions are serializable.
+    /// On failure, returns the transactions forming a dependency cycle.
+    pub fn check_serializable(&self) -> Result<(), Vec<usize>> {
+        let mut edges: Vec<Vec<usize>> = vec![Vec::new(); self.txns.len()];
+
+        // Writers of each key ordered by commit, which is the version order
+        // because a transaction cannot overwrite a version it does not see.
+        let mut key_writers: HashMap<u64, Vec<usize>> = HashMap::new();
+        for &idx in self.commit_order.iter() {
+            let mut keys: Vec<u64> = self.txns[idx]
+                .ops
+                .iter()
+                .filter_map(|op| match op {
+                    Op::Write { key } => Some(*key),
+                    Op::Read { .. } => None,
+                })
+                .collect();
+            keys.sort_unstable();
+            keys.dedup();
+            for key in keys {
+                key_writers.entry(key).or_default().push(idx);
+            }
+        }
+
+        for writers in key_writers.values() {
+            for pair in writers.windows(2) {
+                edges[pair[0]].push(pair[1]);
+            }
+        }
+
+        for &reader in self.commit_order.iter() {
+            for op in self.txns[reader].ops.iter() {
+                if let Op::Read { key, writer } = *op {
+                    if writer == Some(reader) {
+                        continue;
+                    }
+
+                    // Reading a version from an aborted txn is a dirty read,
+                    // which no serial order can explain.
+                    if let Some(w) = writer {
+                        if !self.txns[w].committed {
+                            return Err(vec![w, reader]);
+                        }
+                        edges[w].push(reader);
+                    }
+
+                    // The reader must come before whoever overwrote the version it read.
+                    let writers = key_writers.get(&key).map(Vec::as_slice).unwrap_or(&[]);
+                    let next_pos = match writer {
+                        None => 0,
+                        Some(w) => writers
+                            .iter()
+                            .position(|&x| x == w)
+                            .map_or(writers.len(), |p| p + 1),
+                    };
+                    if let Some(&next) = writers.get(next_pos) {
+                        if next != reader {
+                            edges[reader].push(next);
+                        }
+                    }
+                }
+            }
+        }
+
+        match find_cycle(&edges) {
+            Some(cycle) => Err(cycle),
+            None => Ok(()),
+        }
+    }
+}
+
+fn find_cycle(edges: &[Vec<usize>]) -> Option<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Unvisited,
+        OnPath,
+        Done,
+    }
+
+    fn visit(node: usize, edges: &[Vec<usize>], marks: &mut [Mark], path: &mut Vec<usize>) -> bool {
+        marks[node] = Mark::OnPath;
+        path.push(node);
+        for &next in edges[node].iter() {
+            match marks[next] {
+                Mark::OnPath => {
+                    let start = path.iter().position(|&x| x == next).unwrap_or(0);
+                    path.drain(..start);
+                    return true;
+                }
+                Mark::Unvisited => {
+                    if visit(next, edges, marks, path) {
+                        return true;
+                    }
+                }
+                Mark::Done => {}
+            }
+        }
+        path.pop();
+        marks[node] = Mark::Done;
+        false
+    }
+
+    let mut marks = vec![Mark::Unvisited; edges.len()];
+    let mut path = Vec::new();
+    for node in 0..edges.len() {
+        if marks[node] == Mark::Unvisited && visit(node, edges, &mut marks, &mut path) {
+            return Some(path);
+        }
+    }
+    None
+}
+
+// Xorshift generator, so histories are reproducible from a seed
+// without pulling in a dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_histories_are_serializable() {
+        let configs = [
+            HistoryConfig::default(),
+            HistoryConfig {
+                num_txns: 8,
+                num_keys: 2,
+                ops_per_txn: 3,
+            },
+            HistoryConfig {
+                num_txns: 3,
+                num_keys: 5,
+                ops_per_txn: 8,
+            },
+        ];
+        for config in configs.iter() {
+            for seed in 0..500 {
+                let history = run_random_history(seed, *config);
+                let result = history.check_serializable();
+                assert!(
+                    result.is_ok(),
+                    "Seed {} with {:?} not serializable, cycle {:?}: {:?}",
+                    seed,
+                    config,
+                    result.err(),
+                    history
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_histories_commit_some_txns() {
+        let committed: usize = (0..100)
+            .map(|seed| run_random_history(seed, HistoryConfig::default()))
+            .map(|history| history.commit_order.len())
+            .sum();
+        assert!(committed > 100);
+    }
+
+    #[test]
+    fn test_random_history_without_keys() {
+        let config = HistoryConfig {
+            num_keys: 0,
+            ..HistoryConfig::default()
+        };
+        let history = run_random_history(0, config);
+        assert!(history.txns.is_empty());
+        assert!(history.commit_order.is_empty());
+    }
+
+    #[test]
+    fn test_detects_write_skew() {
+        // Each txn reads the key the other writes, and both commit.
+        let history = History {
+            txns: vec![
+                TxnRecord {
+                    ops: vec![
+                        Op::Read {
+                            key: 0,
+                            writer: None,
+                        },
+                        Op::Write { key: 1 },
+                    ],
+                    committed: true,
+                },
+                TxnRecord {
+                    ops: vec![
+                        Op::Read {
+                            key: 1,
+                            writer: None,
+                        },
+                        Op::Write { key: 0 },
+                    ],
+                    committed: true,
+                },
+            ],
+            commit_order: vec![0, 1],
+        };
+        assert_eq!(history.check_serializable(), Err(vec![0, 1]));
+    }
+
+    #[test]
+    fn test_detects_dirty_read() {
+        let history = History {
+            txns: vec![
+                TxnRecord {
+                    ops: vec![Op::Write { key: 0 }],
+                    committed: false,
+                },
+                TxnRecord {
+                    ops: vec![Op::Read {
+                        key: 0,
+                        writer: Some(0),
+                    }],
+                    committed: true,
+                },
+            ],
+            commit_order: vec![1],
+        };
+        assert_eq!(history.check_serializable(), Err(vec![0, 1]));
+    }
+}
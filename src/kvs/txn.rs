@@ -1,3 +1,4 @@
+use crate::kvs::conflict::ConflictEvent;
 use crate::kvs::error::Error;
 use crate::kvs::key::Key;
 use crate::kvs::keyset::KeySet;
@@ -8,13 +9,38 @@ use std::sync::{Mutex, RwLock};
 
 pub type TxnId = usize;
 
+/// Timestamps of a committed transaction.
+///
+/// Transaction IDs double as begin timestamps: a transaction sees versions
+/// written by transactions with an earlier or equal ID.  The commit timestamp
+/// is drawn from the same clock, so every transaction that begins afterwards
+/// has an ID at or after the commit timestamp.  Commit timestamps are unique
+/// and strictly increasing.  A commit advances the clock only when the
+/// previous commit already took its current value.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TxnInfo {
+    pub begin_ts: TxnId,
+    pub commit_ts: TxnId,
+}
+
 struct Txn<S, K>
 where
     S: KeySpaceId,
     K: Key,
 {
+    label: Option<String>,
     write_set: KeySet<S, K>,
     read_set: KeySet<S, K>,
+    lock_set: KeySet<S, K>,
+}
+
+struct CommittedTxn<S, K>
+where
+    S: KeySpaceId,
+    K: Key,
+{
+    commit_ts: TxnId,
+    txn: Txn<S, K>,
 }
 
 pub struct TxnManager<S, K>
@@ -23,8 +49,12 @@ where
     K: Key,
 {
     next_txn_id: AtomicUsize,
+
+    // Lowest timestamp the next commit may take, so commits never share one.
+    // Only read and written while holding the lock on active txns.
+    next_commit_ts: AtomicUsize,
     active_txns: RwLock<BTreeMap<TxnId, Txn<S, K>>>,
-    recently_committed_txns: Mutex<HashMap<TxnId, Txn<S, K>>>,
+    recently_committed_txns: Mutex<HashMap<TxnId, CommittedTxn<S, K>>>,
 }
 
 impl<S, K> TxnManager<S, K>
@@ -35,6 +65,7 @@ where
     pub fn new() -> TxnManager<S, K> {
         TxnManager {
             next_txn_id: AtomicUsize::new(0),
+            next_commit_ts: AtomicUsize::new(0),
             active_txns: RwLock::new(BTreeMap::new()),
             recently_committed_txns: Mutex::new(HashMap::new()),
         }
@@ -47,32 +78,75 @@ where
             .contains_key(&txn_id)
     }
 
-    pub fn begin_txn(&self) -> TxnId {
-        let txn_id = self.get_next_txn_id();
-
-        self.active_txns
+    pub fn begin_txn(&self, label: Option<String>) -> TxnId {
+        // Allocate the ID while holding the lock so that `stable_ts()`
+        // never observes an ID that has been allocated but not yet registered,
+        // and so that no begin takes an ID while a commit is choosing its
+        // timestamp from the same clock and making its writes visible.
+        let mut active_txns = self
+            .active_txns
             .write()
-            .expect("Could not acquire write lock on active transactions map")
-            .insert(
-                txn_id,
-                Txn {
-                    write_set: KeySet::new(),
-                    read_set: KeySet::new(),
-                },
-            );
+            .expect("Could not acquire write lock on active transactions map");
+
+        let txn_id = self.get_next_txn_id();
+        active_txns.insert(
+            txn_id,
+            Txn {
+                label,
+                write_set: KeySet::new(),
+                read_set: KeySet::new(),
+                lock_set: KeySet::new(),
+            },
+        );
 
         txn_id
     }
 
-    pub fn commit_txn<F, G>(
+    /// Label of an active or recently committed transaction.
+    pub fn label(&self, txn_id: TxnId) -> Option<String> {
+        let active_txns = self
+            .active_txns
+            .read()
+            .expect("Could not acquire read lock on active transactions map");
+        if let Some(txn) = active_txns.get(&txn_id) {
+            return txn.label.clone();
+        }
+        drop(active_txns);
+
+        self.recently_committed_txns
+            .lock()
+            .expect("Could not acquire lock on recently committed txns")
+            .get(&txn_id)
+            .and_then(|committed| committed.txn.label.clone())
+    }
+
+    /// Timestamp before which every transaction has either committed or aborted.
+    /// Reads at an earlier timestamp always see the same versions.
+    pub fn stable_ts(&self) -> TxnId {
+        let active_txns = self
+            .active_txns
+            .read()
+            .expect("Could not acquire read lock on active transactions map");
+
+        match active_txns.keys().next() {
+            Some(min_active_txn_id) => *min_active_txn_id,
+            None => self.next_txn_id.load(Ordering::SeqCst),
+        }
+    }
+
+    pub fn commit_txn<F, G, U, H>(
         &self,
         txn_id: TxnId,
         commit_keys: F,
         abort_keys: G,
-    ) -> Result<(), Error>
+        mut unlock_keys: U,
+        on_conflict: H,
+    ) -> Result<TxnInfo, Error>
     where
         F: FnMut(S, &HashSet<K>),
         G: FnMut(S, &HashSet<K>),
+        U: FnMut(S, &HashSet<K>),
+        H: FnOnce(ConflictEvent<S, K>),
     {
         // Hold exclusive locks on the active transactions map
         // and the recently committed transactions map for the duration
@@ -93,12 +167,24 @@ where
         let min_active_txn_id = active_txns.keys().min();
         let mut discard_txns = Vec::new();
 
-        for (committed_txn_id, committed_txn) in recently_committed_txns.iter() {
+        for (committed_txn_id, committed) in recently_committed_txns.iter() {
             // If another txn wrote a key that this txn read,
             // it could cause a phantom anomaly, so we abort the txn.
-            if *committed_txn_id > begin_ts {
-                if txn.read_set.overlaps(&committed_txn.write_set) {
+            if committed.commit_ts > begin_ts {
+                if let Some((keyspace_id, key)) =
+                    txn.read_set.find_overlap(&committed.txn.write_set)
+                {
                     txn.write_set.for_each_keyspace_keys(abort_keys);
+                    txn.lock_set.for_each_keyspace_keys(&mut unlock_keys);
+                    on_conflict(ConflictEvent {
+                        error: Error::PhantomDetected,
+                        keyspace_id,
+                        key,
+                        txn_id,
+                        txn_label: txn.label,
+                        other_txn_id: Some(*committed_txn_id),
+                        other_txn_label: committed.txn.label.clone(),
+                    });
                     return Err(Error::PhantomDetected);
                 }
             }
@@ -107,7 +193,7 @@ where
             // the oldest active txn, then it can never conflict
             // with an active txn, so we can discard it.
             if let Some(min) = min_active_txn_id {
-                if *committed_txn_id < *min {
+                if committed.commit_ts < *min {
                     discard_txns.push(*committed_txn_id);
                 }
             }
@@ -117,17 +203,31 @@ where
             recently_committed_txns.remove(txn_id);
         }
 
-        // Validation passed, so commit the changes
-        let commit_ts = self.get_next_txn_id();
+        // Validation passed, so commit the changes.
+        // Begins are excluded by the lock on active txns, so taking the current
+        // clock value orders this commit before later begins.  If the last
+        // commit already took it, advance the clock so later begins still
+        // have IDs at or after this commit.
+        let commit_ts = self
+            .next_txn_id
+            .load(Ordering::SeqCst)
+            .max(self.next_commit_ts.load(Ordering::SeqCst));
+        self.next_txn_id.store(commit_ts, Ordering::SeqCst);
+        self.next_commit_ts.store(commit_ts + 1, Ordering::SeqCst);
         txn.write_set.for_each_keyspace_keys(commit_keys);
-        recently_committed_txns.insert(commit_ts, txn);
+        txn.lock_set.for_each_keyspace_keys(unlock_keys);
+        recently_committed_txns.insert(txn_id, CommittedTxn { commit_ts, txn });
 
-        Ok(())
+        Ok(TxnInfo {
+            begin_ts,
+            commit_ts,
+        })
     }
 
-    pub fn abort_txn<F>(&self, txn_id: TxnId, abort_keys: F) -> Result<(), Error>
+    pub fn abort_txn<F, U>(&self, txn_id: TxnId, abort_keys: F, unlock_keys: U) -> Result<(), Error>
     where
         F: FnMut(S, &HashSet<K>),
+        U: FnMut(S, &HashSet<K>),
     {
         let mut active_txns = self
             .active_txns
@@ -135,6 +235,7 @@ where
             .expect("Could not acquire write lock on active transactions map");
         let txn = active_txns.remove(&txn_id).ok_or(Error::InvalidTxnId)?;
         txn.write_set.for_each_keyspace_keys(abort_keys);
+        txn.lock_set.for_each_keyspace_keys(unlock_keys);
         Ok(())
     }
 
@@ -146,6 +247,12 @@ where
         self.run_on_txn(txn_id, |txn| txn.read_set.add_key(keyspace_id, key))
     }
 
+    /// Record a key locked with `Store::get_for_update()` without being written,
+    /// so its lock is released when the txn commits or aborts.
+    pub fn record_lock(&self, txn_id: TxnId, keyspace_id: S, key: &K) {
+        self.run_on_txn(txn_id, |txn| txn.lock_set.add_key(keyspace_id, key))
+    }
+
     fn get_next_txn_id(&self) -> usize {
         self.next_txn_id.fetch_add(1, Ordering::SeqCst)
     }
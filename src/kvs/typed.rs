@@ -0,0 +1,132 @@
+use crate::encode::{Decode, Encode};
+use crate::kvs::error::Error;
+use crate::kvs::handle::KeySpaceHandle;
+use crate::kvs::key::Key;
+use crate::kvs::keyspace::KeySpaceId;
+use crate::kvs::store::Store;
+use crate::kvs::txn::TxnId;
+
+/// A value that knows its own key, so it can be stored without
+/// the caller passing the key separately.
+pub trait Record<K>: Encode + Decode
+where
+    K: Key,
+{
+    fn key(&self) -> K;
+}
+
+/// A keyspace holding records of type `R`, each stored under its own key.
+/// Obtained from `Store::define_record_keyspace()`.
+pub struct RecordHandle<'a, S, K, R>
+where
+    S: KeySpaceId,
+    K: Key,
+{
+    inner: KeySpaceHandle<'a, S, K, R>,
+}
+
+impl<'a, S, K, R> RecordHandle<'a, S, K, R>
+where
+    S: KeySpaceId,
+    K: Key,
+    R: Record<K>,
+{
+    pub(crate) fn new(store: &'a Store<S, K>, keyspace_id: S) -> Self {
+        RecordHandle {
+            inner: KeySpaceHandle::new(store, keyspace_id),
+        }
+    }
+
+    pub fn keyspace_id(&self) -> S {
+        self.inner.keyspace_id()
+    }
+
+    pub fn get(&self, txn_id: TxnId, key: &K) -> Result<Option<R>, Error> {
+        self.inner.get(txn_id, key)
+    }
+
+    /// Insert or replace the record stored under `record.key()`.
+    pub fn put(&self, txn_id: TxnId, record: &R) -> Result<(), Error> {
+        self.inner.set(txn_id, &record.key(), record)
+    }
+
+    pub fn delete(&self, txn_id: TxnId, key: &K) -> Result<(), Error> {
+        self.inner.delete(txn_id, key)
+    }
+}
+
+impl<S, K, R> Clone for RecordHandle<'_, S, K, R>
+where
+    S: KeySpaceId,
+    K: Key,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S, K, R> Copy for RecordHandle<'_, S, K, R>
+where
+    S: KeySpaceId,
+    K: Key,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::{BytesReader, BytesWriter};
+
+    #[derive(Eq, PartialEq, Clone, Copy, Hash)]
+    struct Users {}
+    impl KeySpaceId for Users {}
+
+    #[derive(Debug, PartialEq)]
+    struct User {
+        id: u64,
+        name: String,
+    }
+
+    impl Encode for User {
+        fn encode(&self, w: &mut BytesWriter) {
+            self.id.encode(w);
+            self.name.encode(w);
+        }
+    }
+
+    impl Decode for User {
+        fn decode(r: &mut BytesReader) -> Result<Self, crate::encode::Error> {
+            let id = u64::decode(r)?;
+            let name = String::decode(r)?;
+            Ok(User { id, name })
+        }
+    }
+
+    impl Record<u64> for User {
+        fn key(&self) -> u64 {
+            self.id
+        }
+    }
+
+    #[test]
+    fn test_put_get_delete() {
+        let store: Store<Users, u64> = Store::new();
+        let users = store.define_record_keyspace::<User>(Users {});
+        let alice = User {
+            id: 1,
+            name: "alice".to_string(),
+        };
+
+        let result = store.with_txn(|txn_id| users.put(txn_id, &alice));
+        assert_eq!(result, Ok(()));
+
+        let result = store.with_txn(|txn_id| users.get(txn_id, &1));
+        assert_eq!(result, Ok(Some(alice)));
+
+        let result: Result<Option<User>, Error> = store.with_txn(|txn_id| {
+            users.delete(txn_id, &1)?;
+            users.get(txn_id, &1)
+        });
+        assert_eq!(result, Ok(None));
+    }
+}
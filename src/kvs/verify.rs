@@ -0,0 +1,25 @@
+use crate::kvs::version::VersionId;
+
+/// A broken invariant found by `Store::verify()`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Inconsistency {
+    /// A key or version links to a version that does not exist.
+    DanglingVersion { version_id: VersionId },
+
+    /// A version is reachable from more than one key, or its chain loops.
+    SharedVersion { version_id: VersionId },
+
+    /// A version's value bytes lie outside the value arena.
+    ValueOutOfBounds { version_id: VersionId },
+
+    /// A version's visibility does not match its position in the chain,
+    /// such as an uncommitted version below the head.
+    InvalidVisibility { version_id: VersionId },
+
+    /// A version's write lock does not match the uncommitted version above it.
+    InvalidLockState { version_id: VersionId },
+
+    /// A superseded version's visibility interval does not end
+    /// where the newer version's begins.
+    TimestampsOutOfOrder { version_id: VersionId },
+}
@@ -1,6 +1,8 @@
 use crate::encode::{BytesReader, BytesWriter, Decode, Encode};
 use crate::kvs::error::Error;
 use crate::kvs::txn::TxnId;
+use crate::kvs::verify::Inconsistency;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::RwLock;
 
 pub type VersionId = usize;
@@ -48,8 +50,10 @@ struct VersionEntry {
     // Visibility of this version to transactions
     visibility: VersionVisibility,
 
-    // Last txn to read this version
-    read_ts: TxnId,
+    // Last txn to read this version.
+    // Atomic so that readers can update it while sharing the entry's read lock;
+    // writers check it under the entry's write lock, which excludes readers.
+    read_ts: AtomicUsize,
 
     // Previous version, if any.
     previous: Option<VersionId>,
@@ -74,10 +78,10 @@ impl VersionEntry {
 
             // version is initially visible only to this transaction
             // because it has not yet been committed.
-            visibility: VersionVisibility::OnlyTxn { txn_id: txn_id },
+            visibility: VersionVisibility::OnlyTxn { txn_id },
 
             // current txn is the first to read this version
-            read_ts: txn_id,
+            read_ts: AtomicUsize::new(txn_id),
 
             // link this version to the previous version, if any
             previous,
@@ -100,14 +104,35 @@ impl VersionEntry {
         }
     }
 
-    fn update_read_ts(&mut self, txn_id: TxnId) {
-        if txn_id > self.read_ts {
-            self.read_ts = txn_id;
+    // Whether this version was committed by a transaction that began at or
+    // before `ts`, making it visible to every transaction at or after `ts`
+    // until it is superseded.
+    fn is_committed_at_or_before(&self, ts: TxnId) -> bool {
+        match self.visibility {
+            VersionVisibility::OnlyTxn { .. } => false,
+            VersionVisibility::AnyTxnDuringOrAfter { begin_ts } => begin_ts <= ts,
+            VersionVisibility::AnyTxnWithinTimeInterval { begin_ts, .. } => begin_ts <= ts,
         }
     }
 
+    fn is_unlocked(&self) -> bool {
+        matches!(self.write_lock_state, VersionWriteLockState::Unlocked)
+    }
+
+    fn num_value_bytes(&self) -> usize {
+        self.val_byte_range.end - self.val_byte_range.start
+    }
+
+    fn update_read_ts(&self, txn_id: TxnId) {
+        self.read_ts.fetch_max(txn_id, Ordering::SeqCst);
+    }
+
+    fn read_ts(&self) -> TxnId {
+        self.read_ts.load(Ordering::SeqCst)
+    }
+
     fn acquire_write_lock(&mut self, txn_id: TxnId) -> Result<bool, Error> {
-        if self.read_ts > txn_id {
+        if self.read_ts() > txn_id {
             // cannot update a version that has already been read by a later transaction.
             return Err(Error::ReadWriteConflict);
         }
@@ -200,10 +225,11 @@ impl VersionTable {
             Version::Deleted => (true, EMPTY_VALUE_BYTE_RANGE),
             Version::Value(val) => (false, self.write_value_bytes(val)),
         };
-        let acquired = self.acquire_write_lock(txn_id, prev_version_id)?;
-        if acquired {
-            // acquired the write lock on the previous version,
-            // so create a new version for the uncommitted changes
+        let needs_new_version = self.acquire_write_lock(txn_id, prev_version_id)?;
+        if needs_new_version {
+            // acquired the write lock on the previous version, or already held
+            // it on a committed version, so create a new version for the
+            // uncommitted changes
             let entry = VersionEntry::new_uncommitted(
                 txn_id,
                 Some(prev_version_id),
@@ -238,46 +264,65 @@ impl VersionTable {
     where
         V: Decode,
     {
-        let mut current_id = id;
-        let val_byte_range: ValueByteRange;
-        loop {
-            let entries = self
-                .entries
-                .read()
-                .expect("Could not acquire read lock on entries");
-            match entries.get(current_id) {
-                None => {
-                    return Ok(None);
-                }
-                Some(entry_lock) => {
-                    let mut entry = entry_lock
-                        .write()
-                        .expect("Could not acquire write lock on entry");
-
-                    if entry.is_visible_for_txn(txn_id) {
-                        // found a version visible to this txn
-                        entry.update_read_ts(txn_id);
-                        if entry.is_deleted {
-                            return Ok(None);
-                        } else {
-                            val_byte_range = entry.val_byte_range;
-                            break; // exit the loop to release the lock on entries
-                        }
-                    }
+        self.retrieve_visible(txn_id, id, true)
+    }
 
-                    match entry.previous {
-                        None => {
-                            // no version is visible to this txn
-                            return Ok(None);
-                        }
-                        Some(previous_id) => {
-                            // follow the previous version
-                            current_id = previous_id;
-                        }
-                    }
-                }
-            };
+    /// Retrieve the value visible at timestamp `ts` without recording a read.
+    /// The caller must ensure that no active transaction could still
+    /// commit a version visible at `ts`.
+    pub fn retrieve_as_of<V>(&self, ts: TxnId, id: VersionId) -> Result<Option<V>, Error>
+    where
+        V: Decode,
+    {
+        self.retrieve_visible(ts, id, false)
+    }
+
+    /// Whether the version visible to `txn_id` holds exactly `val`.
+    /// Records a read of the visible version only if it does, since the
+    /// caller then skips the write and depends on the value instead.
+    pub fn visible_value_equals<V>(&self, txn_id: TxnId, id: VersionId, val: &V) -> bool
+    where
+        V: Encode,
+    {
+        let mut val_bytes = Vec::new();
+        val.encode(&mut BytesWriter::new(&mut val_bytes));
+        let (visible_id, val_byte_range) = match self.find_visible_value(txn_id, id, false) {
+            None => return false,
+            Some(visible) => visible,
+        };
+        let is_equal = {
+            let values = self
+                .values
+                .read()
+                .expect("Could not acquire read lock on value bytes");
+            values[val_byte_range.start..val_byte_range.end] == val_bytes[..]
+        };
+        if is_equal {
+            self.entries
+                .read()
+                .expect("Could not acquire read lock on entries")
+                .get(visible_id)
+                .expect("Could not find version")
+                .read()
+                .expect("Could not acquire read lock on entry")
+                .update_read_ts(txn_id);
         }
+        is_equal
+    }
+
+    fn retrieve_visible<V>(
+        &self,
+        txn_id: TxnId,
+        id: VersionId,
+        record_read: bool,
+    ) -> Result<Option<V>, Error>
+    where
+        V: Decode,
+    {
+        let val_byte_range = match self.find_visible_value(txn_id, id, record_read) {
+            None => return Ok(None),
+            Some((_, val_byte_range)) => val_byte_range,
+        };
 
         // Found a non-deleted version visible to this txn, so return its value
         let values = self
@@ -289,6 +334,195 @@ impl VersionTable {
         Ok(Some(val))
     }
 
+    // Version visible to `txn_id` and the byte range of its value,
+    // or None if no version is visible or the visible version is a deletion.
+    fn find_visible_value(
+        &self,
+        txn_id: TxnId,
+        id: VersionId,
+        record_read: bool,
+    ) -> Option<(VersionId, ValueByteRange)> {
+        // Walk the chain under a single read lock on the entries.
+        // The lock is released before reading the value bytes, so a read
+        // holds at most one table-level lock at a time.
+        let entries = self
+            .entries
+            .read()
+            .expect("Could not acquire read lock on entries");
+        let mut current_id = id;
+        loop {
+            let entry = entries
+                .get(current_id)?
+                .read()
+                .expect("Could not acquire read lock on entry");
+
+            if entry.is_visible_for_txn(txn_id) {
+                // found a version visible to this txn
+                if record_read {
+                    entry.update_read_ts(txn_id);
+                }
+                if entry.is_deleted {
+                    return None;
+                }
+                return Some((current_id, entry.val_byte_range));
+            }
+
+            // follow the previous version, if any
+            current_id = entry.previous?;
+        }
+    }
+
+    /// Whether the key whose chain starts at `head_id` can be evicted:
+    /// its latest version is committed and unlocked, nobody after `horizon`
+    /// has read it, and no version is visible at `snapshot_ts`, the newest
+    /// pinned snapshot, if any.
+    pub fn is_evictable(
+        &self,
+        head_id: VersionId,
+        horizon: TxnId,
+        snapshot_ts: Option<TxnId>,
+    ) -> bool {
+        let entries = self
+            .entries
+            .read()
+            .expect("Could not acquire read lock on entries");
+        let head = entries
+            .get(head_id)
+            .expect("Could not find version")
+            .read()
+            .expect("Could not acquire read lock on entry");
+        if !(head.is_committed_at_or_before(horizon)
+            && head.is_unlocked()
+            && head.read_ts() <= horizon)
+        {
+            return false;
+        }
+
+        let snapshot_ts = match snapshot_ts {
+            None => return true,
+            Some(ts) => ts,
+        };
+        // A snapshot sees the newest version committed at or before it,
+        // so the key is evictable only if every version is newer.
+        let mut current_id = Some(head_id);
+        while let Some(id) = current_id {
+            let entry = entries
+                .get(id)
+                .expect("Could not find version")
+                .read()
+                .expect("Could not acquire read lock on entry");
+            if entry.is_committed_at_or_before(snapshot_ts) {
+                return false;
+            }
+            current_id = entry.previous;
+        }
+        true
+    }
+
+    /// Transaction on the other side of a conflict at this version:
+    /// the write lock holder for a write-write conflict,
+    /// or the latest reader for a read-write conflict.
+    pub fn conflicting_txn(&self, id: VersionId, err: &Error) -> Option<TxnId> {
+        let entries = self
+            .entries
+            .read()
+            .expect("Could not acquire read lock on entries");
+        let entry = entries
+            .get(id)?
+            .read()
+            .expect("Could not acquire read lock on entry");
+        match (err, &entry.write_lock_state) {
+            (Error::WriteWriteConflict, VersionWriteLockState::Locked(txn_id)) => Some(*txn_id),
+            (Error::ReadWriteConflict, _) => Some(entry.read_ts()),
+            _ => None,
+        }
+    }
+
+    /// Rebuild the table keeping only the versions reachable from `head_ids`,
+    /// updating each head to its new ID and releasing spare capacity.
+    /// The caller must ensure that nothing else uses the table meanwhile.
+    pub fn compact<'a, I>(&self, head_ids: I)
+    where
+        I: Iterator<Item = &'a mut VersionId>,
+    {
+        let mut entries = self
+            .entries
+            .write()
+            .expect("Could not acquire write lock on entries");
+        let mut values = self
+            .values
+            .write()
+            .expect("Could not acquire write lock on value bytes");
+
+        let mut old_entries: Vec<Option<VersionEntry>> = std::mem::take(&mut *entries)
+            .into_iter()
+            .map(|entry_lock| {
+                Some(
+                    entry_lock
+                        .into_inner()
+                        .expect("Could not acquire lock on entry"),
+                )
+            })
+            .collect();
+        let mut new_entries: Vec<VersionEntry> = Vec::new();
+        let mut new_values = Vec::new();
+
+        for head_id in head_ids {
+            // Copy the chain in order, so each copied version
+            // links to the one copied right after it.
+            let mut old_id = Some(*head_id);
+            *head_id = new_entries.len();
+            while let Some(id) = old_id {
+                let mut entry = old_entries[id]
+                    .take()
+                    .expect("Version is reachable from more than one key");
+                let range = entry.val_byte_range;
+                let start = new_values.len();
+                new_values.extend_from_slice(&values[range.start..range.end]);
+                entry.val_byte_range = ValueByteRange {
+                    start,
+                    end: new_values.len(),
+                };
+
+                old_id = entry.previous;
+                entry.previous = old_id.map(|_| new_entries.len() + 1);
+                new_entries.push(entry);
+            }
+        }
+
+        *entries = new_entries.into_iter().map(RwLock::new).collect();
+        *values = new_values;
+    }
+
+    /// Approximate heap bytes held by the table, including spare capacity.
+    pub fn num_allocated_bytes(&self) -> usize {
+        let entries_capacity = self
+            .entries
+            .read()
+            .expect("Could not acquire read lock on entries")
+            .capacity();
+        let values_capacity = self
+            .values
+            .read()
+            .expect("Could not acquire read lock on value bytes")
+            .capacity();
+        entries_capacity * std::mem::size_of::<RwLock<VersionEntry>>() + values_capacity
+    }
+
+    pub fn num_versions(&self) -> usize {
+        self.entries
+            .read()
+            .expect("Could not acquire read lock on entries")
+            .len()
+    }
+
+    pub fn num_value_bytes(&self) -> usize {
+        self.values
+            .read()
+            .expect("Could not acquire read lock on value bytes")
+            .len()
+    }
+
     pub fn commit(&self, version_id: VersionId) {
         let entries = self
             .entries
@@ -327,17 +561,206 @@ impl VersionTable {
             .read()
             .expect("Could not acquire write lock on entry");
 
-        entry.previous.and_then(|prev_id| {
+        entry.previous.inspect(|&prev_id| {
             let mut prev = entries
                 .get(prev_id)
                 .expect("Could not find previous version")
                 .write()
                 .expect("Could not acquire write lock on prev entry");
             prev.release_write_lock();
-            Some(prev_id)
         })
     }
 
+    /// Take the write lock on the latest version of a key without writing
+    /// a new version.  Returns whether the lock was newly acquired, in which
+    /// case the caller must release it with `unlock()`.
+    pub fn lock(&self, txn_id: TxnId, head_id: VersionId) -> Result<bool, Error> {
+        let entries = self
+            .entries
+            .read()
+            .expect("Could not acquire read lock on entries");
+        let mut entry = entries
+            .get(head_id)
+            .ok_or(Error::VersionNotFound)?
+            .write()
+            .expect("Could not acquire write lock on entry");
+        entry.acquire_write_lock(txn_id)
+    }
+
+    /// Release a lock taken by `lock()`.  Does nothing if the txn no longer
+    /// holds it, because committing or aborting a version the txn wrote
+    /// on top has already released it.
+    pub fn unlock(&self, txn_id: TxnId, head_id: VersionId) {
+        let entries = self
+            .entries
+            .read()
+            .expect("Could not acquire read lock on entries");
+        let mut entry = entries
+            .get(head_id)
+            .expect("Could not find version")
+            .write()
+            .expect("Could not acquire write lock on entry");
+        let is_uncommitted = matches!(entry.visibility, VersionVisibility::OnlyTxn { .. });
+        let is_held =
+            matches!(entry.write_lock_state, VersionWriteLockState::Locked(t) if t == txn_id);
+        if is_held && !is_uncommitted {
+            entry.release_write_lock();
+        }
+    }
+
+    /// Check the invariants of the chains starting at `head_ids`.
+    pub fn verify_chains<I>(&self, head_ids: I) -> Vec<Inconsistency>
+    where
+        I: Iterator<Item = VersionId>,
+    {
+        let entries = self
+            .entries
+            .read()
+            .expect("Could not acquire read lock on entries");
+        let num_value_bytes = self
+            .values
+            .read()
+            .expect("Could not acquire read lock on value bytes")
+            .len();
+
+        let mut problems = Vec::new();
+        let mut visited = vec![false; entries.len()];
+        for head_id in head_ids {
+            // Writer holding the lock on the newest committed version,
+            // and the begin timestamp of the newer committed version, if any.
+            let mut uncommitted_writer: Option<TxnId> = None;
+            let mut newer_begin_ts: Option<TxnId> = None;
+            let mut current_id = Some(head_id);
+
+            while let Some(version_id) = current_id {
+                let entry_lock = match entries.get(version_id) {
+                    None => {
+                        problems.push(Inconsistency::DanglingVersion { version_id });
+                        break;
+                    }
+                    Some(entry_lock) => entry_lock,
+                };
+                if visited[version_id] {
+                    problems.push(Inconsistency::SharedVersion { version_id });
+                    break;
+                }
+                visited[version_id] = true;
+
+                let entry = entry_lock
+                    .read()
+                    .expect("Could not acquire read lock on entry");
+                let range = entry.val_byte_range;
+                if range.start > range.end || range.end > num_value_bytes {
+                    problems.push(Inconsistency::ValueOutOfBounds { version_id });
+                }
+
+                let is_head = version_id == head_id;
+                let is_newest_committed = is_head || uncommitted_writer.is_some();
+                let expected_lock = if is_head {
+                    None
+                } else {
+                    uncommitted_writer.take()
+                };
+                match entry.visibility {
+                    VersionVisibility::OnlyTxn { txn_id } if is_head => {
+                        uncommitted_writer = Some(txn_id);
+                        if !matches!(entry.write_lock_state, VersionWriteLockState::Locked(t) if t == txn_id)
+                        {
+                            problems.push(Inconsistency::InvalidLockState { version_id });
+                        }
+                    }
+                    VersionVisibility::AnyTxnDuringOrAfter { begin_ts } if is_newest_committed => {
+                        newer_begin_ts = Some(begin_ts);
+                        // The head may be locked by a txn that has not written it yet.
+                        let lock_ok = match (expected_lock, &entry.write_lock_state) {
+                            (None, VersionWriteLockState::Unlocked) => true,
+                            (None, VersionWriteLockState::Locked(_)) => is_head,
+                            (Some(writer), VersionWriteLockState::Locked(t)) => writer == *t,
+                            _ => false,
+                        };
+                        if !lock_ok {
+                            problems.push(Inconsistency::InvalidLockState { version_id });
+                        }
+                    }
+                    VersionVisibility::AnyTxnWithinTimeInterval { begin_ts, end_ts }
+                        if !is_newest_committed =>
+                    {
+                        if begin_ts > end_ts || newer_begin_ts != Some(end_ts) {
+                            problems.push(Inconsistency::TimestampsOutOfOrder { version_id });
+                        }
+                        if !entry.is_unlocked() {
+                            problems.push(Inconsistency::InvalidLockState { version_id });
+                        }
+                        newer_begin_ts = Some(begin_ts);
+                    }
+                    _ => problems.push(Inconsistency::InvalidVisibility { version_id }),
+                }
+
+                current_id = entry.previous;
+            }
+        }
+        problems
+    }
+
+    /// Unlink versions in the chain starting at `head_id` that no transaction
+    /// at or after `horizon` can see.  The caller must ensure that every
+    /// transaction at or before `horizon` has finished.
+    ///
+    /// Returns whether the whole chain is unreachable (the key can be removed),
+    /// along with the number of versions and value bytes unlinked.
+    pub fn vacuum_chain(&self, head_id: VersionId, horizon: TxnId) -> (bool, usize, usize) {
+        let entries = self
+            .entries
+            .read()
+            .expect("Could not acquire read lock on entries");
+
+        let mut current_id = head_id;
+        loop {
+            let mut entry = entries
+                .get(current_id)
+                .expect("Could not find version")
+                .write()
+                .expect("Could not acquire write lock on entry");
+
+            if entry.is_committed_at_or_before(horizon) {
+                // Every transaction at or after the horizon sees this version
+                // or a newer one, so older versions are unreachable.
+                let (mut num_versions, mut num_bytes) = (0, 0);
+                let mut prev_opt = entry.previous.take();
+                while let Some(prev_id) = prev_opt {
+                    let prev = entries
+                        .get(prev_id)
+                        .expect("Could not find previous version")
+                        .read()
+                        .expect("Could not acquire read lock on previous entry");
+                    num_versions += 1;
+                    num_bytes += prev.num_value_bytes();
+                    prev_opt = prev.previous;
+                }
+
+                // A committed deletion at the head of the chain can be dropped
+                // entirely once no transaction that read it is still active.
+                let remove_key = current_id == head_id
+                    && entry.is_deleted
+                    && entry.is_unlocked()
+                    && entry.read_ts() <= horizon;
+                if remove_key {
+                    num_versions += 1;
+                }
+
+                return (remove_key, num_versions, num_bytes);
+            }
+
+            match entry.previous {
+                None => return (false, 0, 0),
+                Some(previous_id) => current_id = previous_id,
+            }
+        }
+    }
+
+    // Acquire the write lock on a version, returning whether the txn must
+    // append a new version rather than update this one in place.  A txn can
+    // already hold the lock on a committed version it locked with `lock()`.
     fn acquire_write_lock(&self, txn_id: TxnId, version_id: VersionId) -> Result<bool, Error> {
         let entries = self
             .entries
@@ -350,7 +773,9 @@ impl VersionTable {
             .write()
             .expect("Could not acquire write lock on entry");
 
-        entry.acquire_write_lock(txn_id)
+        let acquired = entry.acquire_write_lock(txn_id)?;
+        let is_uncommitted = matches!(entry.visibility, VersionVisibility::OnlyTxn { .. });
+        Ok(acquired || !is_uncommitted)
     }
 
     fn write_value_bytes<V>(&self, val: &V) -> ValueByteRange
@@ -362,11 +787,109 @@ impl VersionTable {
             .write()
             .expect("Could not acquire write lock on value bytes");
         let start = values.len();
-        let mut w = BytesWriter::new(&mut *values);
+        let mut w = BytesWriter::new(&mut values);
         val.encode(&mut w);
         ValueByteRange {
-            start: start,
+            start,
             end: values.len(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn committed_chain(tbl: &VersionTable) -> VersionId {
+        let first = tbl.append_first_version(0, Version::Value(&"a"));
+        tbl.commit(first);
+        let second = tbl
+            .append_next_version(1, first, Version::Value(&"b"))
+            .expect("Could not append version");
+        tbl.commit(second);
+        tbl.append_next_version(2, second, Version::Value(&"c"))
+            .expect("Could not append version")
+    }
+
+    fn with_entry<F>(tbl: &VersionTable, version_id: VersionId, f: F)
+    where
+        F: FnOnce(&mut VersionEntry),
+    {
+        let entries = tbl.entries.read().expect("Could not lock entries");
+        let mut entry = entries[version_id].write().expect("Could not lock entry");
+        f(&mut entry);
+    }
+
+    #[test]
+    fn test_visible_value_equals_records_read_only_on_match() {
+        let tbl = VersionTable::new();
+        let first = tbl.append_first_version(0, Version::Value(&"a"));
+        tbl.commit(first);
+
+        assert!(!tbl.visible_value_equals(5, first, &"b"));
+        with_entry(&tbl, first, |entry| assert_eq!(entry.read_ts(), 0));
+
+        assert!(tbl.visible_value_equals(5, first, &"a"));
+        with_entry(&tbl, first, |entry| assert_eq!(entry.read_ts(), 5));
+    }
+
+    #[test]
+    fn test_verify_healthy_chain() {
+        let tbl = VersionTable::new();
+        let head = committed_chain(&tbl);
+        assert_eq!(tbl.verify_chains(vec![head].into_iter()), vec![]);
+    }
+
+    #[test]
+    fn test_verify_dangling_and_shared_versions() {
+        let tbl = VersionTable::new();
+        let head = committed_chain(&tbl);
+        assert_eq!(
+            tbl.verify_chains(vec![head, 1, 99].into_iter()),
+            vec![
+                Inconsistency::SharedVersion { version_id: 1 },
+                Inconsistency::DanglingVersion { version_id: 99 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verify_corrupt_entries() {
+        let tbl = VersionTable::new();
+        let head = committed_chain(&tbl);
+        with_entry(&tbl, 0, |entry| {
+            entry.val_byte_range.end = usize::MAX;
+            entry.visibility = VersionVisibility::AnyTxnWithinTimeInterval {
+                begin_ts: 0,
+                end_ts: 5,
+            };
+        });
+        with_entry(&tbl, 1, |entry| {
+            entry.write_lock_state = VersionWriteLockState::Unlocked;
+        });
+        assert_eq!(
+            tbl.verify_chains(vec![head].into_iter()),
+            vec![
+                Inconsistency::InvalidLockState { version_id: 1 },
+                Inconsistency::ValueOutOfBounds { version_id: 0 },
+                Inconsistency::TimestampsOutOfOrder { version_id: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verify_uncommitted_below_head() {
+        let tbl = VersionTable::new();
+        let head = committed_chain(&tbl);
+        with_entry(&tbl, 1, |entry| {
+            entry.visibility = VersionVisibility::OnlyTxn { txn_id: 1 };
+        });
+        assert_eq!(
+            tbl.verify_chains(vec![head].into_iter()),
+            vec![
+                Inconsistency::InvalidVisibility { version_id: 1 },
+                Inconsistency::TimestampsOutOfOrder { version_id: 0 },
+            ]
+        );
+    }
+}
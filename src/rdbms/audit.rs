@@ -0,0 +1,248 @@
+use crate::encode;
+use crate::kvs::Store;
+use crate::kvs::TxnId;
+use crate::rdbms::error::Error;
+use crate::rdbms::key::{Key, KeySpace};
+
+/// A statement recorded in a database's audit log.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// Transaction that executed the statement, which orders records in time.
+    pub txn_id: TxnId,
+
+    /// Authenticated user, or None for an embedded session.
+    pub user: Option<String>,
+
+    /// Kind of statement, such as "CREATE TABLE".
+    pub action: String,
+
+    /// Name of the affected object.
+    pub object: String,
+}
+
+/// Append-only log of statements executed against each database,
+/// plus a system log of statements that affect every database, such as
+/// `CREATE USER`.  Records are written in the executing transaction, so
+/// statements that abort leave no record.  Every append updates the log's
+/// record count, so concurrent audited statements in the same log
+/// conflict and all but one fail with a retryable error.
+pub struct AuditLog<'a> {
+    store: &'a Store<KeySpace, Key>,
+}
+
+impl<'a> AuditLog<'a> {
+    pub fn new(store: &'a Store<KeySpace, Key>) -> AuditLog<'a> {
+        store.define_keyspace(KeySpace::Audit);
+        AuditLog { store }
+    }
+
+    pub fn append(&self, txn_id: TxnId, db_name: &str, record: &AuditRecord) -> Result<(), Error> {
+        self.append_to(txn_id, Some(db_name), record)
+    }
+
+    pub fn len(&self, txn_id: TxnId, db_name: &str) -> Result<u64, Error> {
+        self.len_of(txn_id, Some(db_name))
+    }
+
+    /// Read records `start..` from a database's audit log, oldest first.
+    pub fn read(
+        &self,
+        txn_id: TxnId,
+        db_name: &str,
+        start: u64,
+    ) -> Result<Vec<AuditRecord>, Error> {
+        self.read_from(txn_id, Some(db_name), start)
+    }
+
+    pub fn append_system(&self, txn_id: TxnId, record: &AuditRecord) -> Result<(), Error> {
+        self.append_to(txn_id, None, record)
+    }
+
+    pub fn len_system(&self, txn_id: TxnId) -> Result<u64, Error> {
+        self.len_of(txn_id, None)
+    }
+
+    /// Read records `start..` from the system audit log, oldest first.
+    pub fn read_system(&self, txn_id: TxnId, start: u64) -> Result<Vec<AuditRecord>, Error> {
+        self.read_from(txn_id, None, start)
+    }
+
+    // The log of a database, or the system log if `db_name` is None.
+    fn append_to(
+        &self,
+        txn_id: TxnId,
+        db_name: Option<&str>,
+        record: &AuditRecord,
+    ) -> Result<(), Error> {
+        let len = self.len_of(txn_id, db_name)?;
+        self.store.set(
+            txn_id,
+            KeySpace::Audit,
+            &Self::record_key(db_name, len),
+            record,
+        )?;
+        self.store
+            .set(txn_id, KeySpace::Audit, &Self::len_key(db_name), &(len + 1))
+            .map_err(From::from)
+    }
+
+    fn len_of(&self, txn_id: TxnId, db_name: Option<&str>) -> Result<u64, Error> {
+        let len = self
+            .store
+            .get(txn_id, KeySpace::Audit, &Self::len_key(db_name))?
+            .unwrap_or(0);
+        Ok(len)
+    }
+
+    fn read_from(
+        &self,
+        txn_id: TxnId,
+        db_name: Option<&str>,
+        start: u64,
+    ) -> Result<Vec<AuditRecord>, Error> {
+        let len = self.len_of(txn_id, db_name)?;
+        let mut records = Vec::new();
+        for seq in start..len {
+            let record = self
+                .store
+                .get(txn_id, KeySpace::Audit, &Self::record_key(db_name, seq))?
+                .ok_or(Error::AuditRecordMissing)?;
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    fn len_key(db_name: Option<&str>) -> Key {
+        match db_name {
+            Some(db_name) => Key::AuditLogMeta {
+                db: db_name.to_string(),
+            },
+            None => Key::SystemAuditLogMeta,
+        }
+    }
+
+    fn record_key(db_name: Option<&str>, seq: u64) -> Key {
+        match db_name {
+            Some(db_name) => Key::AuditRecord {
+                db: db_name.to_string(),
+                seq,
+            },
+            None => Key::SystemAuditRecord { seq },
+        }
+    }
+}
+
+impl encode::Encode for AuditRecord {
+    fn encode(&self, w: &mut encode::BytesWriter) {
+        self.txn_id.encode(w);
+        match &self.user {
+            None => false.encode(w),
+            Some(user) => {
+                true.encode(w);
+                user.encode(w);
+            }
+        }
+        self.action.encode(w);
+        self.object.encode(w);
+    }
+}
+
+impl encode::Decode for AuditRecord {
+    fn decode(r: &mut encode::BytesReader) -> Result<Self, encode::Error> {
+        let txn_id = TxnId::decode(r)?;
+        let user = if bool::decode(r)? {
+            Some(String::decode(r)?)
+        } else {
+            None
+        };
+        let action = String::decode(r)?;
+        let object = String::decode(r)?;
+        Ok(AuditRecord {
+            txn_id,
+            user,
+            action,
+            object,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(txn_id: TxnId, object: &str) -> AuditRecord {
+        AuditRecord {
+            txn_id,
+            user: Some("alice".to_string()),
+            action: "CREATE TABLE".to_string(),
+            object: object.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_append_and_read() {
+        let store = Store::new();
+        let audit_log = AuditLog::new(&store);
+        for tbl_name in ["foo", "bar"].iter() {
+            let result = store
+                .with_txn(|txn_id| audit_log.append(txn_id, "testdb", &record(txn_id, tbl_name)));
+            assert_eq!(result, Ok(()));
+        }
+
+        let result = store.with_txn(|txn_id| audit_log.read(txn_id, "testdb", 0));
+        assert_eq!(result, Ok(vec![record(0, "foo"), record(1, "bar")]));
+
+        let result = store.with_txn(|txn_id| audit_log.read(txn_id, "testdb", 1));
+        assert_eq!(result, Ok(vec![record(1, "bar")]));
+
+        let result = store.with_txn(|txn_id| audit_log.read(txn_id, "otherdb", 0));
+        assert_eq!(result, Ok(vec![]));
+    }
+
+    #[test]
+    fn test_system_log_is_separate() {
+        let store = Store::new();
+        let audit_log = AuditLog::new(&store);
+        let result = store.with_txn(|txn_id| {
+            audit_log.append_system(txn_id, &record(txn_id, "foo"))?;
+            audit_log.append(txn_id, "testdb", &record(txn_id, "bar"))
+        });
+        assert_eq!(result, Ok(()));
+
+        let result = store.with_txn(|txn_id| audit_log.read_system(txn_id, 0));
+        assert_eq!(result, Ok(vec![record(0, "foo")]));
+
+        let result = store.with_txn(|txn_id| audit_log.read(txn_id, "testdb", 0));
+        assert_eq!(result, Ok(vec![record(0, "bar")]));
+    }
+
+    #[test]
+    fn test_aborted_append() {
+        let store = Store::new();
+        let audit_log = AuditLog::new(&store);
+        let result: Result<(), Error> = store.with_txn(|txn_id| {
+            audit_log.append(txn_id, "testdb", &record(txn_id, "foo"))?;
+            Err(Error::PermissionDenied)
+        });
+        assert_eq!(result, Err(Error::PermissionDenied));
+
+        let result = store.with_txn(|txn_id| audit_log.len(txn_id, "testdb"));
+        assert_eq!(result, Ok(0));
+    }
+
+    #[test]
+    fn test_read_missing_record() {
+        let store = Store::new();
+        let audit_log = AuditLog::new(&store);
+        let result = store.with_txn(|txn_id| {
+            store.set(
+                txn_id,
+                KeySpace::Audit,
+                &AuditLog::len_key(Some("testdb")),
+                &1u64,
+            )?;
+            audit_log.read(txn_id, "testdb", 0)
+        });
+        assert_eq!(result, Err(Error::AuditRecordMissing));
+    }
+}
@@ -2,20 +2,32 @@ use crate::kvs::Store;
 use crate::kvs::TxnId;
 use crate::rdbms::catalog::column_meta::ColumnMeta;
 use crate::rdbms::catalog::database_meta::DatabaseMeta;
+use crate::rdbms::catalog::grant_meta::{GrantMeta, Privilege};
 use crate::rdbms::catalog::system_meta::SystemMeta;
 use crate::rdbms::catalog::table_meta::TableMeta;
+use crate::rdbms::catalog::user_meta::{RandomSource, UserMeta};
 use crate::rdbms::error::Error;
 use crate::rdbms::key::{Key, KeySpace};
-use crate::rdbms::DataType;
+use crate::rdbms::{Collation, DataType};
 
 pub struct Catalog<'a> {
     store: &'a Store<KeySpace, Key>,
+    random_source: Option<RandomSource>,
 }
 
 impl<'a> Catalog<'a> {
-    pub fn new(store: &'a Store<KeySpace, Key>) -> Catalog {
+    pub fn new(store: &'a Store<KeySpace, Key>) -> Catalog<'a> {
         store.define_keyspace(KeySpace::Catalog);
-        Catalog { store }
+        Catalog {
+            store,
+            random_source: None,
+        }
+    }
+
+    /// Salt password hashes with bytes from `random_source` instead of the
+    /// default, which is available only on unix.
+    pub fn set_random_source(&mut self, random_source: RandomSource) {
+        self.random_source = Some(random_source);
     }
 
     pub fn create_database(&self, txn_id: TxnId, db_name: &str) -> Result<(), Error> {
@@ -40,6 +52,167 @@ impl<'a> Catalog<'a> {
         self.add_col_to_tbl_meta(txn_id, db_name, tbl_name, col_name)
     }
 
+    pub fn create_user(&self, txn_id: TxnId, user_name: &str, password: &str) -> Result<(), Error> {
+        self.add_user_meta(txn_id, user_name, password)?;
+        self.add_user_to_system_meta(txn_id, user_name)
+    }
+
+    /// Change a user's password without checking who is asking;
+    /// sessions use `Session::alter_user_password()`.
+    pub fn alter_user_password(
+        &self,
+        txn_id: TxnId,
+        user_name: &str,
+        password: &str,
+    ) -> Result<(), Error> {
+        let user_meta_key = Key::UserMeta {
+            user: user_name.to_string(),
+        };
+        self.get_user_meta(txn_id, user_name)?;
+        self.store
+            .set(
+                txn_id,
+                KeySpace::Catalog,
+                &user_meta_key,
+                &self.new_user_meta(password)?,
+            )
+            .map_err(From::from)
+    }
+
+    /// Check a user's password, failing with `AuthenticationFailed`
+    /// whether the user doesn't exist or the password is wrong.
+    /// Both cases hash the password, so they take the same time.
+    pub fn authenticate(
+        &self,
+        txn_id: TxnId,
+        user_name: &str,
+        password: &str,
+    ) -> Result<(), Error> {
+        match self.get_user_meta(txn_id, user_name) {
+            Ok(user_meta) if user_meta.check_password(password) => Ok(()),
+            Ok(_) => Err(Error::AuthenticationFailed),
+            Err(Error::UserDoesNotExist { .. }) => {
+                UserMeta::check_password_of_missing_user(password);
+                Err(Error::AuthenticationFailed)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn get_user_meta(&self, txn_id: TxnId, user_name: &str) -> Result<UserMeta, Error> {
+        let user_meta_key = Key::UserMeta {
+            user: user_name.to_string(),
+        };
+        self.store
+            .get::<UserMeta>(txn_id, KeySpace::Catalog, &user_meta_key)?
+            .ok_or_else(|| Error::UserDoesNotExist {
+                user: user_name.to_string(),
+            })
+    }
+
+    pub fn grant(
+        &self,
+        txn_id: TxnId,
+        user_name: &str,
+        privilege: Privilege,
+        db_name: &str,
+        tbl_name: Option<&str>,
+    ) -> Result<(), Error> {
+        self.get_user_meta(txn_id, user_name)?;
+        match tbl_name {
+            None => self.get_database_meta(txn_id, db_name).map(|_| ())?,
+            Some(tbl_name) => self.get_table_meta(txn_id, db_name, tbl_name).map(|_| ())?,
+        }
+        let mut grant_meta = self.get_grant_meta(txn_id, user_name)?;
+        grant_meta.grant(privilege, db_name, tbl_name);
+        self.set_grant_meta(txn_id, user_name, &grant_meta)
+    }
+
+    pub fn revoke(
+        &self,
+        txn_id: TxnId,
+        user_name: &str,
+        privilege: Privilege,
+        db_name: &str,
+        tbl_name: Option<&str>,
+    ) -> Result<(), Error> {
+        self.get_user_meta(txn_id, user_name)?;
+        let mut grant_meta = self.get_grant_meta(txn_id, user_name)?;
+        grant_meta.revoke(privilege, db_name, tbl_name);
+        self.set_grant_meta(txn_id, user_name, &grant_meta)
+    }
+
+    pub fn get_grant_meta(&self, txn_id: TxnId, user_name: &str) -> Result<GrantMeta, Error> {
+        let grant_meta_key = Key::GrantMeta {
+            user: user_name.to_string(),
+        };
+        let grant_meta = self
+            .store
+            .get(txn_id, KeySpace::Catalog, &grant_meta_key)?
+            .unwrap_or_else(GrantMeta::new);
+        Ok(grant_meta)
+    }
+
+    /// Fail with `PermissionDenied` unless the user holds the privilege
+    /// on the table (or on the whole database if `tbl_name` is None).
+    pub fn check_privilege(
+        &self,
+        txn_id: TxnId,
+        user_name: &str,
+        privilege: Privilege,
+        db_name: &str,
+        tbl_name: Option<&str>,
+    ) -> Result<(), Error> {
+        let grant_meta = self.get_grant_meta(txn_id, user_name)?;
+        if grant_meta.has_privilege(privilege, db_name, tbl_name) {
+            Ok(())
+        } else {
+            Err(Error::PermissionDenied)
+        }
+    }
+
+    /// Enable or disable a database's audit log without checking privileges
+    /// or recording the change; sessions use `Session::set_audit_enabled()`.
+    pub fn set_audit_enabled(
+        &self,
+        txn_id: TxnId,
+        db_name: &str,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        let db_meta_key = Key::DatabaseMeta {
+            db: db_name.to_string(),
+        };
+        let mut db_meta = self.get_database_meta(txn_id, db_name)?;
+        db_meta.set_audit_enabled(enabled);
+        self.store
+            .set(txn_id, KeySpace::Catalog, &db_meta_key, &db_meta)
+            .map_err(From::from)
+    }
+
+    /// Set the collation of a text column.
+    pub fn set_column_collation(
+        &self,
+        txn_id: TxnId,
+        db_name: &str,
+        tbl_name: &str,
+        col_name: &str,
+        collation: Collation,
+    ) -> Result<(), Error> {
+        let mut col_meta = self.get_column_meta(txn_id, db_name, tbl_name, col_name)?;
+        if *col_meta.data_type() != DataType::Text {
+            return Err(Error::CollationNotSupported);
+        }
+        col_meta.set_collation(collation);
+        let col_meta_key = Key::ColumnMeta {
+            db: db_name.to_string(),
+            tbl: tbl_name.to_string(),
+            col: col_name.to_string(),
+        };
+        self.store
+            .set(txn_id, KeySpace::Catalog, &col_meta_key, &col_meta)
+            .map_err(From::from)
+    }
+
     pub fn get_system_meta(&self, txn_id: TxnId) -> Result<SystemMeta, Error> {
         self.get_or_create_system_meta(txn_id)
     }
@@ -50,7 +223,9 @@ impl<'a> Catalog<'a> {
         };
         self.store
             .get::<DatabaseMeta>(txn_id, KeySpace::Catalog, &db_meta_key)?
-            .ok_or(Error::DatabaseDoesNotExist)
+            .ok_or_else(|| Error::DatabaseDoesNotExist {
+                db: db_name.to_string(),
+            })
     }
 
     pub fn get_table_meta(
@@ -65,7 +240,10 @@ impl<'a> Catalog<'a> {
         };
         self.store
             .get::<TableMeta>(txn_id, KeySpace::Catalog, &tbl_meta_key)?
-            .ok_or(Error::TableDoesNotExist)
+            .ok_or_else(|| Error::TableDoesNotExist {
+                db: Some(db_name.to_string()),
+                table: tbl_name.to_string(),
+            })
     }
 
     pub fn get_column_meta(
@@ -82,7 +260,11 @@ impl<'a> Catalog<'a> {
         };
         self.store
             .get::<ColumnMeta>(txn_id, KeySpace::Catalog, &col_meta_key)?
-            .ok_or(Error::ColumnDoesNotExist)
+            .ok_or_else(|| Error::ColumnDoesNotExist {
+                db: Some(db_name.to_string()),
+                table: tbl_name.to_string(),
+                column: col_name.to_string(),
+            })
     }
 
     fn get_or_create_system_meta(&self, txn_id: TxnId) -> Result<SystemMeta, Error> {
@@ -101,8 +283,10 @@ impl<'a> Catalog<'a> {
             self.store
                 .get::<DatabaseMeta>(txn_id, KeySpace::Catalog, &db_meta_key)?;
 
-        if let Some(_) = db_meta_opt {
-            return Err(Error::DatabaseAlreadyExists);
+        if db_meta_opt.is_some() {
+            return Err(Error::DatabaseAlreadyExists {
+                db: db_name.to_string(),
+            });
         }
 
         self.store
@@ -125,6 +309,62 @@ impl<'a> Catalog<'a> {
             .map_err(From::from)
     }
 
+    fn add_user_meta(&self, txn_id: TxnId, user_name: &str, password: &str) -> Result<(), Error> {
+        let user_meta_key = Key::UserMeta {
+            user: user_name.to_string(),
+        };
+        let user_meta_opt =
+            self.store
+                .get::<UserMeta>(txn_id, KeySpace::Catalog, &user_meta_key)?;
+
+        if user_meta_opt.is_some() {
+            return Err(Error::UserAlreadyExists {
+                user: user_name.to_string(),
+            });
+        }
+
+        self.store
+            .set(
+                txn_id,
+                KeySpace::Catalog,
+                &user_meta_key,
+                &self.new_user_meta(password)?,
+            )
+            .map_err(From::from)
+    }
+
+    fn new_user_meta(&self, password: &str) -> Result<UserMeta, Error> {
+        match self.random_source {
+            None => UserMeta::new(password),
+            Some(random_source) => UserMeta::with_random_source(password, random_source),
+        }
+        .map_err(|_| Error::RandomUnavailable)
+    }
+
+    fn add_user_to_system_meta(&self, txn_id: TxnId, user_name: &str) -> Result<(), Error> {
+        let mut system_meta: SystemMeta = self.get_or_create_system_meta(txn_id)?;
+
+        system_meta.insert_user_name(user_name);
+
+        self.store
+            .set(txn_id, KeySpace::Catalog, &Key::SystemMeta, &system_meta)
+            .map_err(From::from)
+    }
+
+    fn set_grant_meta(
+        &self,
+        txn_id: TxnId,
+        user_name: &str,
+        grant_meta: &GrantMeta,
+    ) -> Result<(), Error> {
+        let grant_meta_key = Key::GrantMeta {
+            user: user_name.to_string(),
+        };
+        self.store
+            .set(txn_id, KeySpace::Catalog, &grant_meta_key, grant_meta)
+            .map_err(From::from)
+    }
+
     fn add_tbl_meta(&self, txn_id: TxnId, db_name: &str, tbl_name: &str) -> Result<(), Error> {
         let tbl_meta_key = Key::TableMeta {
             db: db_name.to_string(),
@@ -135,8 +375,11 @@ impl<'a> Catalog<'a> {
             .store
             .get::<TableMeta>(txn_id, KeySpace::Catalog, &tbl_meta_key)?;
 
-        if let Some(_) = tbl_meta_opt {
-            return Err(Error::TableAlreadyExists);
+        if tbl_meta_opt.is_some() {
+            return Err(Error::TableAlreadyExists {
+                db: Some(db_name.to_string()),
+                table: tbl_name.to_string(),
+            });
         }
 
         self.store
@@ -157,7 +400,9 @@ impl<'a> Catalog<'a> {
         let mut db_meta = self
             .store
             .get::<DatabaseMeta>(txn_id, KeySpace::Catalog, &db_meta_key)?
-            .ok_or(Error::DatabaseDoesNotExist)?;
+            .ok_or_else(|| Error::DatabaseDoesNotExist {
+                db: db_name.to_string(),
+            })?;
 
         db_meta.insert_tbl_name(tbl_name);
 
@@ -184,8 +429,12 @@ impl<'a> Catalog<'a> {
             self.store
                 .get::<ColumnMeta>(txn_id, KeySpace::Catalog, &col_meta_key)?;
 
-        if let Some(_) = col_meta_opt {
-            return Err(Error::ColumnAlreadyExists);
+        if col_meta_opt.is_some() {
+            return Err(Error::ColumnAlreadyExists {
+                db: Some(db_name.to_string()),
+                table: tbl_name.to_string(),
+                column: col_name.to_string(),
+            });
         }
 
         self.store
@@ -213,7 +462,10 @@ impl<'a> Catalog<'a> {
         let mut tbl_meta = self
             .store
             .get::<TableMeta>(txn_id, KeySpace::Catalog, &tbl_meta_key)?
-            .ok_or(Error::TableDoesNotExist)?;
+            .ok_or_else(|| Error::TableDoesNotExist {
+                db: Some(db_name.to_string()),
+                table: tbl_name.to_string(),
+            })?;
 
         tbl_meta.insert_col_name(col_name);
 
@@ -233,10 +485,10 @@ mod tests {
         let catalog = Catalog::new(&store);
         let db_name = "testdb";
         let result: Result<DatabaseMeta, Error> = store.with_txn(|txn_id| {
-            catalog.create_database(txn_id, &db_name)?;
-            catalog.get_database_meta(txn_id, &db_name)
+            catalog.create_database(txn_id, db_name)?;
+            catalog.get_database_meta(txn_id, db_name)
         });
-        assert_eq!(result.is_ok(), true, "Error occurred: {:?}", result.err());
+        assert!(result.is_ok(), "Error occurred: {:?}", result.err());
     }
 
     #[test]
@@ -245,11 +497,16 @@ mod tests {
         let catalog = Catalog::new(&store);
         let db_name = "testdb";
         let result: Result<(), Error> = store.with_txn(|txn_id| {
-            catalog.create_database(txn_id, &db_name)?;
-            catalog.create_database(txn_id, &db_name)?;
+            catalog.create_database(txn_id, db_name)?;
+            catalog.create_database(txn_id, db_name)?;
             Ok(())
         });
-        assert_eq!(result, Err(Error::DatabaseAlreadyExists));
+        assert_eq!(
+            result,
+            Err(Error::DatabaseAlreadyExists {
+                db: db_name.to_string()
+            })
+        );
     }
 
     #[test]
@@ -259,7 +516,7 @@ mod tests {
         let db_names = vec!["testdb1", "testdb2", "testdb3"];
         let result: Result<Vec<String>, Error> = store.with_txn(|txn_id| {
             for db_name in db_names.iter() {
-                catalog.create_database(txn_id, &db_name)?;
+                catalog.create_database(txn_id, db_name)?;
             }
             let system_meta: SystemMeta = catalog.get_system_meta(txn_id)?;
             let retrieved_db_names: Vec<String> =
@@ -274,8 +531,13 @@ mod tests {
     fn test_get_database_does_not_exist() {
         let store = Store::new();
         let catalog = Catalog::new(&store);
-        let result = store.with_txn(|txn_id| catalog.get_database_meta(txn_id, &"notexist"));
-        assert_eq!(result, Err(Error::DatabaseDoesNotExist));
+        let result = store.with_txn(|txn_id| catalog.get_database_meta(txn_id, "notexist"));
+        assert_eq!(
+            result,
+            Err(Error::DatabaseDoesNotExist {
+                db: "notexist".to_string()
+            })
+        );
     }
 
     #[test]
@@ -287,7 +549,7 @@ mod tests {
             assert_eq!(system_meta.iter_db_names().len(), 0);
             Ok(())
         });
-        assert_eq!(result.is_ok(), true, "Error occurred: {:?}", result.err());
+        assert!(result.is_ok(), "Error occurred: {:?}", result.err());
     }
 
     #[test]
@@ -295,12 +557,12 @@ mod tests {
         let store = Store::new();
         let catalog = Catalog::new(&store);
         let db_name = "testdb";
-        let tbl_names = vec!["foo", "bar", "baz"];
+        let tbl_names = ["foo", "bar", "baz"];
         let result: Result<(), Error> = store.with_txn(|txn_id| {
-            catalog.create_database(txn_id, &db_name)?;
+            catalog.create_database(txn_id, db_name)?;
 
             for t in tbl_names.iter() {
-                catalog.create_table(txn_id, &db_name, &t)?;
+                catalog.create_table(txn_id, db_name, t)?;
             }
 
             for t in tbl_names.iter() {
@@ -309,7 +571,7 @@ mod tests {
 
             Ok(())
         });
-        assert_eq!(result.is_ok(), true, "Error occurred: {:?}", result.err());
+        assert!(result.is_ok(), "Error occurred: {:?}", result.err());
     }
 
     #[test]
@@ -317,8 +579,13 @@ mod tests {
         let store = Store::new();
         let catalog = Catalog::new(&store);
         let result: Result<(), Error> =
-            store.with_txn(|txn_id| catalog.create_table(txn_id, &"notexists", &"foo"));
-        assert_eq!(result, Err(Error::DatabaseDoesNotExist));
+            store.with_txn(|txn_id| catalog.create_table(txn_id, "notexists", "foo"));
+        assert_eq!(
+            result,
+            Err(Error::DatabaseDoesNotExist {
+                db: "notexists".to_string()
+            })
+        );
     }
 
     #[test]
@@ -328,11 +595,17 @@ mod tests {
         let db_name = "testdb";
         let tbl_name = "testtbl";
         let result: Result<(), Error> = store.with_txn(|txn_id| {
-            catalog.create_database(txn_id, &db_name)?;
-            catalog.create_table(txn_id, &db_name, &tbl_name)?;
-            catalog.create_table(txn_id, &db_name, &tbl_name)
+            catalog.create_database(txn_id, db_name)?;
+            catalog.create_table(txn_id, db_name, tbl_name)?;
+            catalog.create_table(txn_id, db_name, tbl_name)
         });
-        assert_eq!(result, Err(Error::TableAlreadyExists));
+        assert_eq!(
+            result,
+            Err(Error::TableAlreadyExists {
+                db: Some(db_name.to_string()),
+                table: tbl_name.to_string()
+            })
+        );
     }
 
     #[test]
@@ -342,13 +615,13 @@ mod tests {
         let db_name = "testdb";
         let mut tbl_names = vec!["foo", "bar", "baz"];
         let result: Result<Vec<String>, Error> = store.with_txn(|txn_id| {
-            catalog.create_database(txn_id, &db_name)?;
+            catalog.create_database(txn_id, db_name)?;
 
             for t in tbl_names.iter() {
-                catalog.create_table(txn_id, &db_name, &t)?;
+                catalog.create_table(txn_id, db_name, t)?;
             }
 
-            let db_meta = catalog.get_database_meta(txn_id, &db_name)?;
+            let db_meta = catalog.get_database_meta(txn_id, db_name)?;
             let tbl_names: Vec<String> = db_meta.iter_tbl_names().map(|s| s.to_string()).collect();
             Ok(tbl_names)
         });
@@ -364,8 +637,8 @@ mod tests {
         let catalog = Catalog::new(&store);
         let db_name = "testdb";
         let result: Result<DatabaseMeta, Error> = store.with_txn(|txn_id| {
-            catalog.create_database(txn_id, &db_name)?;
-            catalog.get_database_meta(txn_id, &db_name)
+            catalog.create_database(txn_id, db_name)?;
+            catalog.get_database_meta(txn_id, db_name)
         });
         let db_meta = result.expect("Could not retrieve db meta");
         assert_eq!(db_meta.iter_tbl_names().len(), 0);
@@ -379,11 +652,11 @@ mod tests {
         let tbl_name = "testtbl";
         let col_name = "testcol";
         let result: Result<(), Error> = store.with_txn(|txn_id| {
-            catalog.create_database(txn_id, &db_name)?;
-            catalog.create_table(txn_id, &db_name, &tbl_name)?;
-            catalog.create_column(txn_id, &db_name, &tbl_name, &col_name, DataType::Int64)
+            catalog.create_database(txn_id, db_name)?;
+            catalog.create_table(txn_id, db_name, tbl_name)?;
+            catalog.create_column(txn_id, db_name, tbl_name, col_name, DataType::Int64)
         });
-        assert_eq!(result.is_ok(), true, "Error occurred {:?}", result.err());
+        assert!(result.is_ok(), "Error occurred {:?}", result.err());
     }
 
     #[test]
@@ -394,9 +667,15 @@ mod tests {
         let tbl_name = "testtbl";
         let col_name = "testcol";
         let result: Result<(), Error> = store.with_txn(|txn_id| {
-            catalog.create_column(txn_id, &db_name, &tbl_name, &col_name, DataType::Int64)
+            catalog.create_column(txn_id, db_name, tbl_name, col_name, DataType::Int64)
         });
-        assert_eq!(result, Err(Error::TableDoesNotExist));
+        assert_eq!(
+            result,
+            Err(Error::TableDoesNotExist {
+                db: Some(db_name.to_string()),
+                table: tbl_name.to_string()
+            })
+        );
     }
 
     #[test]
@@ -407,12 +686,19 @@ mod tests {
         let tbl_name = "testtbl";
         let col_name = "testcol";
         let result: Result<(), Error> = store.with_txn(|txn_id| {
-            catalog.create_database(txn_id, &db_name)?;
-            catalog.create_table(txn_id, &db_name, &tbl_name)?;
-            catalog.create_column(txn_id, &db_name, &tbl_name, &col_name, DataType::Int64)?;
-            catalog.create_column(txn_id, &db_name, &tbl_name, &col_name, DataType::Int64)
+            catalog.create_database(txn_id, db_name)?;
+            catalog.create_table(txn_id, db_name, tbl_name)?;
+            catalog.create_column(txn_id, db_name, tbl_name, col_name, DataType::Int64)?;
+            catalog.create_column(txn_id, db_name, tbl_name, col_name, DataType::Int64)
         });
-        assert_eq!(result, Err(Error::ColumnAlreadyExists));
+        assert_eq!(
+            result,
+            Err(Error::ColumnAlreadyExists {
+                db: Some(db_name.to_string()),
+                table: tbl_name.to_string(),
+                column: col_name.to_string()
+            })
+        );
     }
 
     #[test]
@@ -423,12 +709,12 @@ mod tests {
         let tbl_name = "testtbl";
         let mut col_names = vec!["foo", "bar", "baz"];
         let result: Result<Vec<String>, Error> = store.with_txn(|txn_id| {
-            catalog.create_database(txn_id, &db_name)?;
-            catalog.create_table(txn_id, &db_name, &tbl_name)?;
+            catalog.create_database(txn_id, db_name)?;
+            catalog.create_table(txn_id, db_name, tbl_name)?;
             for c in col_names.iter() {
-                catalog.create_column(txn_id, &db_name, &tbl_name, &c, DataType::Int64)?;
+                catalog.create_column(txn_id, db_name, tbl_name, c, DataType::Int64)?;
             }
-            let tbl_meta = catalog.get_table_meta(txn_id, &db_name, &tbl_name)?;
+            let tbl_meta = catalog.get_table_meta(txn_id, db_name, tbl_name)?;
             let retrieved_col_names: Vec<String> =
                 tbl_meta.iter_col_names().map(|s| s.to_string()).collect();
             Ok(retrieved_col_names)
@@ -445,12 +731,196 @@ mod tests {
         let db_name = "testdb";
         let tbl_name = "testtbl";
         let result: Result<(), Error> = store.with_txn(|txn_id| {
-            catalog.create_database(txn_id, &db_name)?;
-            catalog.create_table(txn_id, &db_name, &tbl_name)?;
-            let tbl_meta = catalog.get_table_meta(txn_id, &db_name, &tbl_name)?;
+            catalog.create_database(txn_id, db_name)?;
+            catalog.create_table(txn_id, db_name, tbl_name)?;
+            let tbl_meta = catalog.get_table_meta(txn_id, db_name, tbl_name)?;
             assert_eq!(tbl_meta.iter_col_names().len(), 0);
             Ok(())
         });
-        assert_eq!(result.is_ok(), true, "Error occurred: {:?}", result.err());
+        assert!(result.is_ok(), "Error occurred: {:?}", result.err());
+    }
+
+    #[test]
+    fn test_create_user_and_authenticate() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        let result: Result<Vec<String>, Error> = store.with_txn(|txn_id| {
+            catalog.create_user(txn_id, "alice", "secret")?;
+            catalog.create_user(txn_id, "bob", "hunter2")?;
+            catalog.authenticate(txn_id, "alice", "secret")?;
+            let system_meta = catalog.get_system_meta(txn_id)?;
+            Ok(system_meta.iter_user_names().cloned().collect())
+        });
+        assert_eq!(result, Ok(vec!["alice".to_string(), "bob".to_string()]));
+    }
+
+    #[test]
+    fn test_create_user_with_random_source() {
+        fn counter(buf: &mut [u8]) -> std::io::Result<()> {
+            for (i, b) in buf.iter_mut().enumerate() {
+                *b = i as u8;
+            }
+            Ok(())
+        }
+        fn unavailable(_buf: &mut [u8]) -> std::io::Result<()> {
+            Err(std::io::ErrorKind::Unsupported.into())
+        }
+
+        let store = Store::new();
+        let mut catalog = Catalog::new(&store);
+        catalog.set_random_source(unavailable);
+        let result = store.with_txn(|txn_id| catalog.create_user(txn_id, "alice", "secret"));
+        assert_eq!(result, Err(Error::RandomUnavailable));
+
+        catalog.set_random_source(counter);
+        let result = store.with_txn(|txn_id| {
+            catalog.create_user(txn_id, "alice", "secret")?;
+            catalog.authenticate(txn_id, "alice", "secret")
+        });
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_create_user_already_exists() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        let result: Result<(), Error> = store.with_txn(|txn_id| {
+            catalog.create_user(txn_id, "alice", "secret")?;
+            catalog.create_user(txn_id, "alice", "other")
+        });
+        assert_eq!(
+            result,
+            Err(Error::UserAlreadyExists {
+                user: "alice".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_authenticate_failures() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        let result: Result<(), Error> = store.with_txn(|txn_id| {
+            catalog.create_user(txn_id, "alice", "secret")?;
+            assert_eq!(
+                catalog.authenticate(txn_id, "alice", "wrong"),
+                Err(Error::AuthenticationFailed)
+            );
+            assert_eq!(
+                catalog.authenticate(txn_id, "nobody", "secret"),
+                Err(Error::AuthenticationFailed)
+            );
+            Ok(())
+        });
+        assert!(result.is_ok(), "Error occurred: {:?}", result.err());
+    }
+
+    #[test]
+    fn test_alter_user_password() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        let result: Result<(), Error> = store.with_txn(|txn_id| {
+            catalog.create_user(txn_id, "alice", "secret")?;
+            catalog.alter_user_password(txn_id, "alice", "newsecret")?;
+            assert_eq!(
+                catalog.authenticate(txn_id, "alice", "secret"),
+                Err(Error::AuthenticationFailed)
+            );
+            catalog.authenticate(txn_id, "alice", "newsecret")?;
+            catalog.alter_user_password(txn_id, "nobody", "secret")
+        });
+        assert_eq!(
+            result,
+            Err(Error::UserDoesNotExist {
+                user: "nobody".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_grant_and_revoke() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        let result: Result<(), Error> = store.with_txn(|txn_id| {
+            catalog.create_user(txn_id, "alice", "secret")?;
+            catalog.create_database(txn_id, "testdb")?;
+            catalog.create_table(txn_id, "testdb", "foo")?;
+            assert_eq!(
+                catalog.check_privilege(txn_id, "alice", Privilege::Select, "testdb", Some("foo")),
+                Err(Error::PermissionDenied)
+            );
+
+            catalog.grant(txn_id, "alice", Privilege::Select, "testdb", Some("foo"))?;
+            catalog.check_privilege(txn_id, "alice", Privilege::Select, "testdb", Some("foo"))?;
+
+            catalog.revoke(txn_id, "alice", Privilege::Select, "testdb", Some("foo"))?;
+            catalog.check_privilege(txn_id, "alice", Privilege::Select, "testdb", Some("foo"))
+        });
+        assert_eq!(result, Err(Error::PermissionDenied));
+    }
+
+    #[test]
+    fn test_grant_object_does_not_exist() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        let result: Result<(), Error> = store.with_txn(|txn_id| {
+            catalog.create_database(txn_id, "testdb")?;
+            assert_eq!(
+                catalog.grant(txn_id, "nobody", Privilege::Select, "testdb", None),
+                Err(Error::UserDoesNotExist {
+                    user: "nobody".to_string()
+                })
+            );
+            catalog.create_user(txn_id, "alice", "secret")?;
+            assert_eq!(
+                catalog.grant(txn_id, "alice", Privilege::Select, "notexist", None),
+                Err(Error::DatabaseDoesNotExist {
+                    db: "notexist".to_string()
+                })
+            );
+            catalog.grant(
+                txn_id,
+                "alice",
+                Privilege::Select,
+                "testdb",
+                Some("notexist"),
+            )
+        });
+        assert_eq!(
+            result,
+            Err(Error::TableDoesNotExist {
+                db: Some("testdb".to_string()),
+                table: "notexist".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_set_column_collation() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        let result: Result<(), Error> = store.with_txn(|txn_id| {
+            catalog.create_database(txn_id, "testdb")?;
+            catalog.create_table(txn_id, "testdb", "testtbl")?;
+            catalog.create_column(txn_id, "testdb", "testtbl", "name", DataType::Text)?;
+            catalog.create_column(txn_id, "testdb", "testtbl", "num", DataType::Int64)?;
+            catalog.set_column_collation(
+                txn_id,
+                "testdb",
+                "testtbl",
+                "name",
+                Collation::UnicodeCaseInsensitive,
+            )?;
+            let col_meta = catalog.get_column_meta(txn_id, "testdb", "testtbl", "name")?;
+            assert_eq!(col_meta.collation(), Collation::UnicodeCaseInsensitive);
+            catalog.set_column_collation(
+                txn_id,
+                "testdb",
+                "testtbl",
+                "num",
+                Collation::AsciiCaseInsensitive,
+            )
+        });
+        assert_eq!(result, Err(Error::CollationNotSupported));
     }
 }
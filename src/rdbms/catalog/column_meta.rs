@@ -1,26 +1,47 @@
 use crate::encode;
-use crate::rdbms::DataType;
+use crate::rdbms::{Collation, DataType};
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct ColumnMeta {
     data_type: DataType,
+    collation: Collation,
 }
 
 impl ColumnMeta {
     pub fn new(data_type: DataType) -> ColumnMeta {
-        ColumnMeta { data_type }
+        ColumnMeta {
+            data_type,
+            collation: Collation::default(),
+        }
+    }
+
+    pub fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    pub fn collation(&self) -> Collation {
+        self.collation
+    }
+
+    pub fn set_collation(&mut self, collation: Collation) {
+        self.collation = collation;
     }
 }
 
 impl encode::Encode for ColumnMeta {
     fn encode(&self, w: &mut encode::BytesWriter) {
         self.data_type.encode(w);
+        self.collation.encode(w);
     }
 }
 
 impl encode::Decode for ColumnMeta {
     fn decode(r: &mut encode::BytesReader) -> Result<Self, encode::Error> {
         let data_type = DataType::decode(r)?;
-        Ok(ColumnMeta { data_type })
+        let collation = Collation::decode(r)?;
+        Ok(ColumnMeta {
+            data_type,
+            collation,
+        })
     }
 }
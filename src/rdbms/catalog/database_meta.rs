@@ -3,12 +3,20 @@ use crate::encode;
 #[derive(Debug, PartialEq, Eq)]
 pub struct DatabaseMeta {
     tbl_names: Vec<String>,
+    audit_enabled: bool,
+}
+
+impl Default for DatabaseMeta {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DatabaseMeta {
     pub fn new() -> DatabaseMeta {
         DatabaseMeta {
             tbl_names: Vec::new(),
+            audit_enabled: false,
         }
     }
 
@@ -21,17 +29,30 @@ impl DatabaseMeta {
     pub fn iter_tbl_names(&self) -> std::slice::Iter<'_, std::string::String> {
         self.tbl_names.iter()
     }
+
+    pub fn audit_enabled(&self) -> bool {
+        self.audit_enabled
+    }
+
+    pub fn set_audit_enabled(&mut self, enabled: bool) {
+        self.audit_enabled = enabled;
+    }
 }
 
 impl encode::Encode for DatabaseMeta {
     fn encode(&self, w: &mut encode::BytesWriter) {
-        self.tbl_names.encode(w)
+        self.tbl_names.encode(w);
+        self.audit_enabled.encode(w);
     }
 }
 
 impl encode::Decode for DatabaseMeta {
     fn decode(r: &mut encode::BytesReader) -> Result<Self, encode::Error> {
         let tbl_names = Vec::<String>::decode(r)?;
-        Ok(DatabaseMeta { tbl_names })
+        let audit_enabled = bool::decode(r)?;
+        Ok(DatabaseMeta {
+            tbl_names,
+            audit_enabled,
+        })
     }
 }
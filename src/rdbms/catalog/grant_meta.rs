@@ -0,0 +1,195 @@
+use crate::encode;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Privilege {
+    Select,
+    Insert,
+    Update,
+    Delete,
+    Ddl,
+}
+
+impl fmt::Display for Privilege {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Privilege::Select => write!(f, "SELECT"),
+            Privilege::Insert => write!(f, "INSERT"),
+            Privilege::Update => write!(f, "UPDATE"),
+            Privilege::Delete => write!(f, "DELETE"),
+            Privilege::Ddl => write!(f, "DDL"),
+        }
+    }
+}
+
+impl Privilege {
+    fn mask(self) -> u8 {
+        match self {
+            Privilege::Select => 1,
+            Privilege::Insert => 1 << 1,
+            Privilege::Update => 1 << 2,
+            Privilege::Delete => 1 << 3,
+            Privilege::Ddl => 1 << 4,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct GrantEntry {
+    db: String,
+    tbl: Option<String>,
+    privileges: u8,
+}
+
+/// Privileges granted to a single user.
+/// A grant on a database applies to every table in that database.
+#[derive(Debug, PartialEq, Eq)]
+pub struct GrantMeta {
+    entries: Vec<GrantEntry>,
+}
+
+impl GrantMeta {
+    pub fn new() -> GrantMeta {
+        GrantMeta {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn grant(&mut self, privilege: Privilege, db: &str, tbl: Option<&str>) {
+        match self.find_entry_mut(db, tbl) {
+            Some(entry) => entry.privileges |= privilege.mask(),
+            None => self.entries.push(GrantEntry {
+                db: db.to_string(),
+                tbl: tbl.map(|t| t.to_string()),
+                privileges: privilege.mask(),
+            }),
+        }
+    }
+
+    pub fn revoke(&mut self, privilege: Privilege, db: &str, tbl: Option<&str>) {
+        if let Some(entry) = self.find_entry_mut(db, tbl) {
+            entry.privileges &= !privilege.mask();
+        }
+        self.entries.retain(|entry| entry.privileges != 0);
+    }
+
+    pub fn has_privilege(&self, privilege: Privilege, db: &str, tbl: Option<&str>) -> bool {
+        self.entries.iter().any(|entry| {
+            entry.db == db
+                && (entry.tbl.is_none() || entry.tbl.as_deref() == tbl)
+                && entry.privileges & privilege.mask() != 0
+        })
+    }
+
+    /// Whether the user holds any privilege on the table, or on anything
+    /// in the database if `tbl` is None.  Users can see only such objects.
+    pub fn has_any_privilege(&self, db: &str, tbl: Option<&str>) -> bool {
+        self.entries.iter().any(|entry| {
+            entry.db == db && (tbl.is_none() || entry.tbl.is_none() || entry.tbl.as_deref() == tbl)
+        })
+    }
+
+    fn find_entry_mut(&mut self, db: &str, tbl: Option<&str>) -> Option<&mut GrantEntry> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.db == db && entry.tbl.as_deref() == tbl)
+    }
+}
+
+impl Default for GrantMeta {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl encode::Encode for GrantEntry {
+    fn encode(&self, w: &mut encode::BytesWriter) {
+        self.db.encode(w);
+        match &self.tbl {
+            None => false.encode(w),
+            Some(tbl) => {
+                true.encode(w);
+                tbl.encode(w);
+            }
+        }
+        self.privileges.encode(w);
+    }
+}
+
+impl encode::Decode for GrantEntry {
+    fn decode(r: &mut encode::BytesReader) -> Result<Self, encode::Error> {
+        let db = String::decode(r)?;
+        let tbl = if bool::decode(r)? {
+            Some(String::decode(r)?)
+        } else {
+            None
+        };
+        let privileges = u8::decode(r)?;
+        Ok(GrantEntry {
+            db,
+            tbl,
+            privileges,
+        })
+    }
+}
+
+impl encode::Encode for GrantMeta {
+    fn encode(&self, w: &mut encode::BytesWriter) {
+        self.entries.encode(w)
+    }
+}
+
+impl encode::Decode for GrantMeta {
+    fn decode(r: &mut encode::BytesReader) -> Result<Self, encode::Error> {
+        let entries = Vec::<GrantEntry>::decode(r)?;
+        Ok(GrantMeta { entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::{Decode, Encode};
+
+    #[test]
+    fn test_grant_and_revoke() {
+        let mut meta = GrantMeta::new();
+        meta.grant(Privilege::Select, "db", None);
+        meta.grant(Privilege::Insert, "db", Some("foo"));
+        assert!(meta.has_privilege(Privilege::Select, "db", Some("foo")));
+        assert!(meta.has_privilege(Privilege::Select, "db", None));
+        assert!(meta.has_privilege(Privilege::Insert, "db", Some("foo")));
+        assert!(!meta.has_privilege(Privilege::Insert, "db", Some("bar")));
+        assert!(!meta.has_privilege(Privilege::Insert, "db", None));
+        assert!(!meta.has_privilege(Privilege::Select, "otherdb", None));
+
+        meta.revoke(Privilege::Select, "db", None);
+        assert!(!meta.has_privilege(Privilege::Select, "db", Some("foo")));
+        assert!(meta.has_privilege(Privilege::Insert, "db", Some("foo")));
+    }
+
+    #[test]
+    fn test_has_any_privilege() {
+        let mut meta = GrantMeta::new();
+        meta.grant(Privilege::Select, "db", Some("foo"));
+        meta.grant(Privilege::Ddl, "otherdb", None);
+        assert!(meta.has_any_privilege("db", None));
+        assert!(meta.has_any_privilege("db", Some("foo")));
+        assert!(!meta.has_any_privilege("db", Some("bar")));
+        assert!(meta.has_any_privilege("otherdb", Some("bar")));
+        assert!(!meta.has_any_privilege("thirddb", None));
+    }
+
+    #[test]
+    fn test_encode_and_decode() {
+        let mut meta = GrantMeta::new();
+        meta.grant(Privilege::Select, "db", None);
+        meta.grant(Privilege::Delete, "db", Some("foo"));
+        meta.grant(Privilege::Ddl, "db", Some("foo"));
+        let mut buf = Vec::new();
+        meta.encode(&mut encode::BytesWriter::new(&mut buf));
+        let decoded =
+            GrantMeta::decode(&mut encode::BytesReader::new(&buf)).expect("Could not decode");
+        assert_eq!(meta, decoded);
+    }
+}
@@ -1,11 +1,16 @@
+#[allow(clippy::module_inception)]
 mod catalog;
 mod column_meta;
 mod database_meta;
+mod grant_meta;
 mod system_meta;
 mod table_meta;
+mod user_meta;
 
 pub use catalog::Catalog;
 pub use column_meta::ColumnMeta;
 pub use database_meta::DatabaseMeta;
+pub use grant_meta::{GrantMeta, Privilege};
 pub use system_meta::SystemMeta;
 pub use table_meta::TableMeta;
+pub use user_meta::{RandomSource, UserMeta};
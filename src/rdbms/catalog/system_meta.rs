@@ -2,12 +2,20 @@ use crate::encode;
 
 pub struct SystemMeta {
     db_names: Vec<String>,
+    user_names: Vec<String>,
+}
+
+impl Default for SystemMeta {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SystemMeta {
     pub fn new() -> SystemMeta {
         SystemMeta {
             db_names: Vec::new(),
+            user_names: Vec::new(),
         }
     }
 
@@ -20,17 +28,32 @@ impl SystemMeta {
     pub fn iter_db_names(&self) -> std::slice::Iter<'_, std::string::String> {
         self.db_names.iter()
     }
+
+    pub fn insert_user_name(&mut self, name: &str) {
+        if let Err(idx) = self.user_names.binary_search_by(|n| n.as_str().cmp(name)) {
+            self.user_names.insert(idx, name.to_string())
+        }
+    }
+
+    pub fn iter_user_names(&self) -> std::slice::Iter<'_, std::string::String> {
+        self.user_names.iter()
+    }
 }
 
 impl encode::Encode for SystemMeta {
     fn encode(&self, w: &mut encode::BytesWriter) {
         self.db_names.encode(w);
+        self.user_names.encode(w);
     }
 }
 
 impl encode::Decode for SystemMeta {
     fn decode(r: &mut encode::BytesReader) -> Result<Self, encode::Error> {
         let db_names = Vec::<String>::decode(r)?;
-        Ok(SystemMeta { db_names })
+        let user_names = Vec::<String>::decode(r)?;
+        Ok(SystemMeta {
+            db_names,
+            user_names,
+        })
     }
 }
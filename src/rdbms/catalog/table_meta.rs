@@ -5,6 +5,12 @@ pub struct TableMeta {
     col_names: Vec<String>,
 }
 
+impl Default for TableMeta {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TableMeta {
     pub fn new() -> TableMeta {
         TableMeta {
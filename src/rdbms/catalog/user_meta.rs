@@ -0,0 +1,448 @@
+use crate::encode;
+use std::io;
+
+const SALT_LEN: usize = 16;
+
+// Salt hashed against when a user doesn't exist.  Its value doesn't matter,
+// since the hash is never compared to anything.
+const DUMMY_SALT: [u8; SALT_LEN] = [0; SALT_LEN];
+const HASH_LEN: usize = 32;
+
+// OWASP's recommendation for PBKDF2-HMAC-SHA256.  Tests use fewer
+// iterations to stay fast; the count is stored with each hash either way.
+const HASH_ITERATIONS: u32 = if cfg!(test) { 1_000 } else { 600_000 };
+
+const PBKDF2_HMAC_SHA256_CODE: u8 = 1;
+
+/// Key derivation function used to hash a password.  Stored with the hash
+/// so the scheme can be changed without invalidating existing passwords.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum HashAlgorithm {
+    Pbkdf2HmacSha256 { iterations: u32 },
+}
+
+impl HashAlgorithm {
+    fn hash(&self, salt: &[u8], password: &str) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Pbkdf2HmacSha256 { iterations } => {
+                let mut out = vec![0; HASH_LEN];
+                pbkdf2_hmac_sha256(password.as_bytes(), salt, *iterations, &mut out);
+                out
+            }
+        }
+    }
+}
+
+impl encode::Encode for HashAlgorithm {
+    fn encode(&self, w: &mut encode::BytesWriter) {
+        match self {
+            HashAlgorithm::Pbkdf2HmacSha256 { iterations } => {
+                PBKDF2_HMAC_SHA256_CODE.encode(w);
+                iterations.encode(w);
+            }
+        }
+    }
+}
+
+impl encode::Decode for HashAlgorithm {
+    fn decode(r: &mut encode::BytesReader) -> Result<Self, encode::Error> {
+        match u8::decode(r)? {
+            PBKDF2_HMAC_SHA256_CODE => Ok(HashAlgorithm::Pbkdf2HmacSha256 {
+                iterations: u32::decode(r)?,
+            }),
+            _ => Err(encode::Error::InvalidFormat(
+                "Unrecognized password hash algorithm",
+            )),
+        }
+    }
+}
+
+/// Fills a buffer with cryptographically secure random bytes, used to salt
+/// password hashes.  The default reads /dev/urandom on unix; embedders on
+/// other targets, such as Windows or wasm32, must supply their own.
+pub type RandomSource = fn(&mut [u8]) -> io::Result<()>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct UserMeta {
+    algorithm: HashAlgorithm,
+    salt: Vec<u8>,
+    password_hash: Vec<u8>,
+}
+
+impl UserMeta {
+    /// Hash `password` with a new random salt from the default random
+    /// source.  Fails if the operating system's random number generator
+    /// is unavailable, as it always is on targets other than unix.
+    pub fn new(password: &str) -> io::Result<UserMeta> {
+        Self::with_random_source(password, fill_random)
+    }
+
+    /// Hash `password` with a new salt from `random_source`.
+    pub fn with_random_source(password: &str, random_source: RandomSource) -> io::Result<UserMeta> {
+        let mut salt = vec![0; SALT_LEN];
+        random_source(&mut salt)?;
+        let algorithm = HashAlgorithm::Pbkdf2HmacSha256 {
+            iterations: HASH_ITERATIONS,
+        };
+        let password_hash = algorithm.hash(&salt, password);
+        Ok(UserMeta {
+            algorithm,
+            salt,
+            password_hash,
+        })
+    }
+
+    /// Hash `password` as `check_password()` would and reject it, for
+    /// users that don't exist.  Logins then take the same time whether or
+    /// not the user exists, so the time doesn't reveal which users exist.
+    pub fn check_password_of_missing_user(password: &str) -> bool {
+        let algorithm = HashAlgorithm::Pbkdf2HmacSha256 {
+            iterations: HASH_ITERATIONS,
+        };
+        std::hint::black_box(algorithm.hash(&DUMMY_SALT, password));
+        false
+    }
+
+    pub fn check_password(&self, password: &str) -> bool {
+        let candidate = self.algorithm.hash(&self.salt, password);
+        // Compare every byte so the time taken doesn't reveal the matching prefix.
+        candidate.len() == self.password_hash.len()
+            && candidate
+                .iter()
+                .zip(self.password_hash.iter())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+impl encode::Encode for UserMeta {
+    fn encode(&self, w: &mut encode::BytesWriter) {
+        self.algorithm.encode(w);
+        self.salt.encode(w);
+        self.password_hash.encode(w);
+    }
+}
+
+impl encode::Decode for UserMeta {
+    fn decode(r: &mut encode::BytesReader) -> Result<Self, encode::Error> {
+        let algorithm = HashAlgorithm::decode(r)?;
+        let salt = Vec::<u8>::decode(r)?;
+        let password_hash = Vec::<u8>::decode(r)?;
+        Ok(UserMeta {
+            algorithm,
+            salt,
+            password_hash,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn fill_random(buf: &mut [u8]) -> io::Result<()> {
+    use std::io::Read;
+    std::fs::File::open("/dev/urandom")?.read_exact(buf)
+}
+
+#[cfg(not(unix))]
+fn fill_random(_buf: &mut [u8]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "no default random source on this target; supply one with set_random_source()",
+    ))
+}
+
+/// PBKDF2 (RFC 8018) with HMAC-SHA256 as the pseudorandom function.
+fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    let prf = HmacSha256::new(password);
+    for (i, block) in out.chunks_mut(HASH_LEN).enumerate() {
+        let mut msg = salt.to_vec();
+        msg.extend_from_slice(&(i as u32 + 1).to_be_bytes());
+        let mut u = prf.mac(&msg);
+        let mut t = u;
+        for _ in 1..iterations {
+            u = prf.mac(&u);
+            for (x, y) in t.iter_mut().zip(u.iter()) {
+                *x ^= y;
+            }
+        }
+        block.copy_from_slice(&t[..block.len()]);
+    }
+}
+
+/// HMAC (RFC 2104) over SHA-256, keyed once and reused for many messages.
+struct HmacSha256 {
+    inner: Sha256,
+    outer: Sha256,
+}
+
+impl HmacSha256 {
+    fn new(key: &[u8]) -> HmacSha256 {
+        let mut block = [0u8; 64];
+        if key.len() > block.len() {
+            block[..HASH_LEN].copy_from_slice(&sha256(key));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner = Sha256::new();
+        let mut outer = Sha256::new();
+        inner.update(&block.map(|b| b ^ 0x36));
+        outer.update(&block.map(|b| b ^ 0x5c));
+        HmacSha256 { inner, outer }
+    }
+
+    fn mac(&self, msg: &[u8]) -> [u8; HASH_LEN] {
+        let mut inner = self.inner.clone();
+        inner.update(msg);
+        let mut outer = self.outer.clone();
+        outer.update(&inner.finish());
+        outer.finish()
+    }
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 (FIPS 180-4) over data supplied incrementally.
+#[derive(Clone)]
+struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    block_len: usize,
+    total_len: u64,
+}
+
+impl Sha256 {
+    fn new() -> Sha256 {
+        Sha256 {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            block: [0; 64],
+            block_len: 0,
+            total_len: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.total_len = self.total_len.wrapping_add(data.len() as u64);
+        while !data.is_empty() {
+            let n = (self.block.len() - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + n].copy_from_slice(&data[..n]);
+            self.block_len += n;
+            data = &data[n..];
+            if self.block_len == self.block.len() {
+                sha256_compress(&mut self.state, &self.block);
+                self.block_len = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> [u8; HASH_LEN] {
+        let bit_len = self.total_len.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.block_len != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bit_len.to_be_bytes());
+
+        let mut out = [0u8; HASH_LEN];
+        for (i, x) in self.state.iter().enumerate() {
+            out[i * 4..i * 4 + 4].copy_from_slice(&x.to_be_bytes());
+        }
+        out
+    }
+}
+
+fn sha256(data: &[u8]) -> [u8; HASH_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+fn sha256_compress(h: &mut [u32; 8], chunk: &[u8; 64]) {
+    let mut w = [0u32; 64];
+    for (i, word) in chunk.chunks(4).enumerate() {
+        w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = *h;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = hh
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(SHA256_K[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        hh = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+
+    for (x, y) in h.iter_mut().zip([a, b, c, d, e, f, g, hh].iter()) {
+        *x = x.wrapping_add(*y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            hex(&sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(&sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_sha256_multiple_blocks() {
+        let data = [b'a'; 1000];
+        let mut hasher = Sha256::new();
+        for chunk in data.chunks(7) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finish(), sha256(&data));
+        assert_eq!(
+            hex(&sha256(&data)),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test cases 1, 2 and 6.
+        assert_eq!(
+            hex(&HmacSha256::new(&[0x0b; 20]).mac(b"Hi There")),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            hex(&HmacSha256::new(b"Jefe").mac(b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex(&HmacSha256::new(&[0xaa; 131])
+                .mac(b"Test Using Larger Than Block-Size Key - Hash Key First")),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn test_pbkdf2_hmac_sha256() {
+        // RFC 7914, section 11.
+        let mut out = [0u8; 64];
+        pbkdf2_hmac_sha256(b"passwd", b"salt", 1, &mut out);
+        assert_eq!(
+            hex(&out),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc\
+             49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+        );
+        pbkdf2_hmac_sha256(b"Password", b"NaCl", 80000, &mut out);
+        assert_eq!(
+            hex(&out),
+            "4ddcd8f60b98be21830cee5ef22701f9641a4418d04c0414aeff08876b34ab56\
+             a1d425a1225833549adb841b51c9b3176a272bdebba1d078478f62b397f33c8d"
+        );
+    }
+
+    #[test]
+    fn test_encode_and_decode() {
+        use crate::encode::{Decode, Encode};
+
+        let user_meta = UserMeta::new("secret").expect("Could not hash password");
+        let mut buf = Vec::new();
+        user_meta.encode(&mut encode::BytesWriter::new(&mut buf));
+        assert_eq!(&buf[..5], &[1, 0xe8, 0x03, 0, 0]);
+        let decoded = UserMeta::decode(&mut encode::BytesReader::new(&buf))
+            .expect("Could not decode user meta");
+        assert_eq!(decoded, user_meta);
+        assert!(decoded.check_password("secret"));
+
+        buf[0] = 9;
+        assert_eq!(
+            UserMeta::decode(&mut encode::BytesReader::new(&buf)),
+            Err(encode::Error::InvalidFormat(
+                "Unrecognized password hash algorithm"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_check_password() {
+        let user_meta = UserMeta::new("secret").expect("Could not hash password");
+        assert!(user_meta.check_password("secret"));
+        assert!(!user_meta.check_password("Secret"));
+        assert!(!user_meta.check_password(""));
+    }
+
+    #[test]
+    fn test_check_password_of_missing_user() {
+        assert!(!UserMeta::check_password_of_missing_user("secret"));
+        assert!(!UserMeta::check_password_of_missing_user(""));
+    }
+
+    #[test]
+    fn test_with_random_source() {
+        fn fill_sevens(buf: &mut [u8]) -> io::Result<()> {
+            buf.fill(7);
+            Ok(())
+        }
+        fn fail(_buf: &mut [u8]) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "no randomness"))
+        }
+
+        let user_meta =
+            UserMeta::with_random_source("secret", fill_sevens).expect("Could not hash password");
+        assert_eq!(user_meta.salt, vec![7; SALT_LEN]);
+        assert!(user_meta.check_password("secret"));
+        assert!(UserMeta::with_random_source("secret", fail).is_err());
+    }
+
+    #[test]
+    fn test_salts_differ() {
+        let u1 = UserMeta::new("secret").expect("Could not hash password");
+        let u2 = UserMeta::new("secret").expect("Could not hash password");
+        assert_ne!(u1.salt, u2.salt);
+        assert_ne!(u1.password_hash, u2.password_hash);
+    }
+}
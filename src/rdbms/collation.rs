@@ -0,0 +1,145 @@
+use crate::encode;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// How string values in a column are compared, sorted and tested for uniqueness.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub enum Collation {
+    /// Compare UTF-8 bytes.
+    #[default]
+    Binary,
+
+    /// Ignore case of ASCII letters only.
+    AsciiCaseInsensitive,
+
+    /// Ignore case using Unicode lowercase mappings.
+    UnicodeCaseInsensitive,
+}
+
+const BINARY_CODE: u8 = 0;
+const ASCII_CASE_INSENSITIVE_CODE: u8 = 1;
+const UNICODE_CASE_INSENSITIVE_CODE: u8 = 2;
+
+impl Collation {
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        match self {
+            Collation::Binary => a.cmp(b),
+            Collation::AsciiCaseInsensitive => a
+                .bytes()
+                .map(|c| c.to_ascii_lowercase())
+                .cmp(b.bytes().map(|c| c.to_ascii_lowercase())),
+            Collation::UnicodeCaseInsensitive => a
+                .chars()
+                .flat_map(char::to_lowercase)
+                .cmp(b.chars().flat_map(char::to_lowercase)),
+        }
+    }
+
+    /// Transform a string so that comparing the results bytewise gives the
+    /// same order as `compare()`.  Used to build ordered keys and unique indexes.
+    pub fn sort_key(&self, s: &str) -> String {
+        match self {
+            Collation::Binary => s.to_string(),
+            Collation::AsciiCaseInsensitive => s.to_ascii_lowercase(),
+            Collation::UnicodeCaseInsensitive => s.chars().flat_map(char::to_lowercase).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Collation::Binary => write!(f, "binary"),
+            Collation::AsciiCaseInsensitive => write!(f, "ascii_ci"),
+            Collation::UnicodeCaseInsensitive => write!(f, "unicode_ci"),
+        }
+    }
+}
+
+impl encode::Encode for Collation {
+    fn encode(&self, w: &mut encode::BytesWriter) {
+        let code = match self {
+            Collation::Binary => BINARY_CODE,
+            Collation::AsciiCaseInsensitive => ASCII_CASE_INSENSITIVE_CODE,
+            Collation::UnicodeCaseInsensitive => UNICODE_CASE_INSENSITIVE_CODE,
+        };
+        code.encode(w)
+    }
+}
+
+impl encode::Decode for Collation {
+    fn decode(r: &mut encode::BytesReader) -> Result<Self, encode::Error> {
+        let code = u8::decode(r)?;
+        match code {
+            BINARY_CODE => Ok(Collation::Binary),
+            ASCII_CASE_INSENSITIVE_CODE => Ok(Collation::AsciiCaseInsensitive),
+            UNICODE_CASE_INSENSITIVE_CODE => Ok(Collation::UnicodeCaseInsensitive),
+            _ => Err(encode::Error::InvalidFormat("Unrecognized collation")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::{Decode, Encode};
+
+    fn check_encode_and_decode(input: Collation) {
+        let mut buf = Vec::new();
+        let mut w = encode::BytesWriter::new(&mut buf);
+        input.encode(&mut w);
+        let mut r = encode::BytesReader::new(&buf);
+        let output = Collation::decode(&mut r).expect("Could not decode");
+        assert_eq!(input, output);
+    }
+
+    #[test]
+    fn it_encodes_collations() {
+        check_encode_and_decode(Collation::Binary);
+        check_encode_and_decode(Collation::AsciiCaseInsensitive);
+        check_encode_and_decode(Collation::UnicodeCaseInsensitive);
+    }
+
+    #[test]
+    fn test_binary() {
+        let c = Collation::Binary;
+        assert_eq!(c.compare("abc", "abc"), Ordering::Equal);
+        assert_eq!(c.compare("ABC", "abc"), Ordering::Less);
+        assert_eq!(c.sort_key("AbC"), "AbC");
+    }
+
+    #[test]
+    fn test_ascii_case_insensitive() {
+        let c = Collation::AsciiCaseInsensitive;
+        assert_eq!(c.compare("ABC", "abc"), Ordering::Equal);
+        assert_eq!(c.compare("abc", "ABD"), Ordering::Less);
+        assert_eq!(c.compare("ÄBC", "äbc"), Ordering::Less);
+        assert_eq!(c.sort_key("AbC"), "abc");
+    }
+
+    #[test]
+    fn test_unicode_case_insensitive() {
+        let c = Collation::UnicodeCaseInsensitive;
+        assert_eq!(c.compare("ÄBC", "äbc"), Ordering::Equal);
+        assert_eq!(c.compare("straße", "STRASSE"), Ordering::Greater);
+        assert_eq!(c.sort_key("ÄbC"), "äbc");
+    }
+
+    #[test]
+    fn test_sort_key_order_matches_compare() {
+        let words = ["b", "A", "a", "Ä", "ä", "B", "ab", "AC"];
+        for c in [
+            Collation::Binary,
+            Collation::AsciiCaseInsensitive,
+            Collation::UnicodeCaseInsensitive,
+        ]
+        .iter()
+        {
+            for a in words.iter() {
+                for b in words.iter() {
+                    assert_eq!(c.compare(a, b), c.sort_key(a).cmp(&c.sort_key(b)));
+                }
+            }
+        }
+    }
+}
@@ -1,16 +1,29 @@
 use crate::encode;
+use std::fmt;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum DataType {
     Int64,
+    Text,
 }
 
 const INT64_CODE: u8 = 0;
+const TEXT_CODE: u8 = 1;
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataType::Int64 => write!(f, "BIGINT"),
+            DataType::Text => write!(f, "TEXT"),
+        }
+    }
+}
 
 impl encode::Encode for DataType {
     fn encode(&self, w: &mut encode::BytesWriter) {
         let code = match self {
             DataType::Int64 => INT64_CODE,
+            DataType::Text => TEXT_CODE,
         };
         code.encode(w)
     }
@@ -21,6 +34,7 @@ impl encode::Decode for DataType {
         let code = u8::decode(r)?;
         match code {
             INT64_CODE => Ok(DataType::Int64),
+            TEXT_CODE => Ok(DataType::Text),
             _ => Err(encode::Error::InvalidFormat("Unrecognized datatype")),
         }
     }
@@ -44,4 +58,9 @@ mod tests {
     fn it_encodes_int64_type() {
         check_encode_and_decode(DataType::Int64);
     }
+
+    #[test]
+    fn it_encodes_text_type() {
+        check_encode_and_decode(DataType::Text);
+    }
 }
@@ -0,0 +1,208 @@
+use crate::kvs::TxnId;
+use crate::rdbms::catalog::Catalog;
+use crate::rdbms::error::Error;
+use crate::rdbms::Collation;
+use std::fmt::Write;
+
+/// Produce the SQL statements that recreate a database's schema.
+pub fn dump_sql(catalog: &Catalog, txn_id: TxnId, db_name: &str) -> Result<String, Error> {
+    let mut out = String::new();
+    let db_meta = catalog.get_database_meta(txn_id, db_name)?;
+    let db_ident = quote_ident(db_name);
+    writeln!(out, "CREATE DATABASE {};", db_ident).expect("Could not write to string");
+
+    for tbl_name in db_meta.iter_tbl_names() {
+        let tbl_meta = catalog.get_table_meta(txn_id, db_name, tbl_name)?;
+        let mut col_defs = Vec::new();
+        for col_name in tbl_meta.iter_col_names() {
+            let col_meta = catalog.get_column_meta(txn_id, db_name, tbl_name, col_name)?;
+            let mut col_def = format!("    {} {}", quote_ident(col_name), col_meta.data_type());
+            if col_meta.collation() != Collation::Binary {
+                write!(col_def, " COLLATE {}", col_meta.collation())
+                    .expect("Could not write to string");
+            }
+            col_defs.push(col_def);
+        }
+
+        write!(
+            out,
+            "\nCREATE TABLE {}.{} (",
+            db_ident,
+            quote_ident(tbl_name)
+        )
+        .expect("Could not write to string");
+        if !col_defs.is_empty() {
+            write!(out, "\n{}\n", col_defs.join(",\n")).expect("Could not write to string");
+        }
+        writeln!(out, ");").expect("Could not write to string");
+    }
+
+    Ok(out)
+}
+
+// Reserved words that cannot be used as identifiers unless quoted,
+// in sorted order for binary search.
+const KEYWORDS: &[&str] = &[
+    "all",
+    "alter",
+    "and",
+    "any",
+    "as",
+    "asc",
+    "between",
+    "bigint",
+    "by",
+    "case",
+    "check",
+    "collate",
+    "column",
+    "constraint",
+    "create",
+    "database",
+    "default",
+    "delete",
+    "desc",
+    "distinct",
+    "drop",
+    "else",
+    "end",
+    "false",
+    "foreign",
+    "from",
+    "grant",
+    "group",
+    "having",
+    "in",
+    "index",
+    "insert",
+    "into",
+    "is",
+    "join",
+    "key",
+    "like",
+    "limit",
+    "not",
+    "null",
+    "offset",
+    "on",
+    "or",
+    "order",
+    "primary",
+    "references",
+    "revoke",
+    "select",
+    "set",
+    "table",
+    "text",
+    "then",
+    "to",
+    "true",
+    "union",
+    "unique",
+    "update",
+    "user",
+    "values",
+    "when",
+    "where",
+    "with",
+];
+
+/// Quote an identifier unless it is a plain lowercase name
+/// that is not a reserved word.
+fn quote_ident(name: &str) -> String {
+    let is_plain = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && KEYWORDS.binary_search(&name).is_err();
+
+    if is_plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kvs::Store;
+    use crate::rdbms::DataType;
+
+    #[test]
+    fn test_dump_sql() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        let result: Result<String, Error> = store.with_txn(|txn_id| {
+            catalog.create_database(txn_id, "testdb")?;
+            catalog.create_table(txn_id, "testdb", "foo")?;
+            catalog.create_table(txn_id, "testdb", "Bar")?;
+            catalog.create_column(txn_id, "testdb", "foo", "x", DataType::Int64)?;
+            catalog.create_column(txn_id, "testdb", "foo", "my \"y\"", DataType::Int64)?;
+            catalog.create_column(txn_id, "testdb", "foo", "z", DataType::Text)?;
+            catalog.set_column_collation(
+                txn_id,
+                "testdb",
+                "foo",
+                "z",
+                Collation::AsciiCaseInsensitive,
+            )?;
+            dump_sql(&catalog, txn_id, "testdb")
+        });
+        let expected = concat!(
+            "CREATE DATABASE testdb;\n",
+            "\n",
+            "CREATE TABLE testdb.\"Bar\" ();\n",
+            "\n",
+            "CREATE TABLE testdb.foo (\n",
+            "    \"my \"\"y\"\"\" BIGINT,\n",
+            "    x BIGINT,\n",
+            "    z TEXT COLLATE ascii_ci\n",
+            ");\n",
+        );
+        assert_eq!(result, Ok(expected.to_string()));
+    }
+
+    #[test]
+    fn test_dump_sql_quotes_keywords() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        let result: Result<String, Error> = store.with_txn(|txn_id| {
+            catalog.create_database(txn_id, "testdb")?;
+            catalog.create_table(txn_id, "testdb", "order")?;
+            catalog.create_column(txn_id, "testdb", "order", "select", DataType::Int64)?;
+            catalog.create_column(txn_id, "testdb", "order", "selected", DataType::Int64)?;
+            dump_sql(&catalog, txn_id, "testdb")
+        });
+        let expected = concat!(
+            "CREATE DATABASE testdb;\n",
+            "\n",
+            "CREATE TABLE testdb.\"order\" (\n",
+            "    \"select\" BIGINT,\n",
+            "    selected BIGINT\n",
+            ");\n",
+        );
+        assert_eq!(result, Ok(expected.to_string()));
+    }
+
+    #[test]
+    fn test_keywords_are_sorted() {
+        assert!(KEYWORDS.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_dump_sql_database_does_not_exist() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        let result = store.with_txn(|txn_id| dump_sql(&catalog, txn_id, "notexist"));
+        assert_eq!(
+            result,
+            Err(Error::DatabaseDoesNotExist {
+                db: "notexist".to_string()
+            })
+        );
+    }
+}
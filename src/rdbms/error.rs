@@ -3,12 +3,148 @@ use crate::kvs;
 #[derive(Debug, PartialEq, Eq)]
 pub enum Error {
     KvsError(kvs::Error),
-    DatabaseAlreadyExists,
-    DatabaseDoesNotExist,
-    TableAlreadyExists,
-    TableDoesNotExist,
-    ColumnAlreadyExists,
-    ColumnDoesNotExist,
+    DatabaseAlreadyExists {
+        db: String,
+    },
+    DatabaseDoesNotExist {
+        db: String,
+    },
+
+    // `db` is None for temporary tables, which belong to no database.
+    TableAlreadyExists {
+        db: Option<String>,
+        table: String,
+    },
+    TableDoesNotExist {
+        db: Option<String>,
+        table: String,
+    },
+    ColumnAlreadyExists {
+        db: Option<String>,
+        table: String,
+        column: String,
+    },
+    ColumnDoesNotExist {
+        db: Option<String>,
+        table: String,
+        column: String,
+    },
+    CollationNotSupported,
+    UserAlreadyExists {
+        user: String,
+    },
+    UserDoesNotExist {
+        user: String,
+    },
+    AuthenticationFailed,
+    PermissionDenied,
+    TxnAlreadyInProgress,
+    NoTxnInProgress,
+    NoDatabaseSelected,
+    QueryCancelled,
+    StatementTimeout,
+    NotificationsUnavailable,
+    RandomUnavailable,
+    AuditRecordMissing,
+}
+
+impl Error {
+    /// Whether the transaction failed because it conflicted with
+    /// a concurrent transaction, in which case the client can retry it.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Error::KvsError(err) if err.is_conflict())
+    }
+
+    /// Stable SQLSTATE-style code identifying the kind of error,
+    /// following PostgreSQL's assignments where one exists.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::KvsError(err) => err.code(),
+            Error::DatabaseAlreadyExists { .. } => "42P04",
+            Error::DatabaseDoesNotExist { .. } => "3D000",
+            Error::TableAlreadyExists { .. } => "42P07",
+            Error::TableDoesNotExist { .. } => "42P01",
+            Error::ColumnAlreadyExists { .. } => "42701",
+            Error::ColumnDoesNotExist { .. } => "42703",
+            Error::CollationNotSupported => "0A000",
+            Error::UserAlreadyExists { .. } => "42710",
+            Error::UserDoesNotExist { .. } => "42704",
+            Error::AuthenticationFailed => "28P01",
+            Error::PermissionDenied => "42501",
+            Error::TxnAlreadyInProgress => "25001",
+            Error::NoTxnInProgress => "25P01",
+            Error::NoDatabaseSelected => "3D000",
+            Error::QueryCancelled | Error::StatementTimeout => "57014",
+            Error::NotificationsUnavailable => "55000",
+            Error::RandomUnavailable => "58000",
+            Error::AuditRecordMissing => "XX001",
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            // The cause is available through `source()`.
+            Error::KvsError(_) => write!(f, "storage error"),
+            Error::DatabaseAlreadyExists { db } => write!(f, "database \"{}\" already exists", db),
+            Error::DatabaseDoesNotExist { db } => write!(f, "database \"{}\" does not exist", db),
+            Error::TableAlreadyExists { db, table } => {
+                write!(f, "table \"{}\" already exists", qualified(db, table))
+            }
+            Error::TableDoesNotExist { db, table } => {
+                write!(f, "table \"{}\" does not exist", qualified(db, table))
+            }
+            Error::ColumnAlreadyExists { db, table, column } => write!(
+                f,
+                "column \"{}\" of table \"{}\" already exists",
+                column,
+                qualified(db, table)
+            ),
+            Error::ColumnDoesNotExist { db, table, column } => write!(
+                f,
+                "column \"{}\" of table \"{}\" does not exist",
+                column,
+                qualified(db, table)
+            ),
+            Error::CollationNotSupported => {
+                write!(f, "collation is not supported for the column's data type")
+            }
+            Error::UserAlreadyExists { user } => write!(f, "user \"{}\" already exists", user),
+            Error::UserDoesNotExist { user } => write!(f, "user \"{}\" does not exist", user),
+            Error::AuthenticationFailed => write!(f, "authentication failed"),
+            Error::PermissionDenied => write!(f, "permission denied"),
+            Error::TxnAlreadyInProgress => write!(f, "a transaction is already in progress"),
+            Error::NoTxnInProgress => write!(f, "no transaction is in progress"),
+            Error::NoDatabaseSelected => write!(f, "no database selected"),
+            Error::QueryCancelled => write!(f, "query was cancelled"),
+            Error::StatementTimeout => write!(f, "statement timed out"),
+            Error::NotificationsUnavailable => {
+                write!(f, "session is not connected to a notification hub")
+            }
+            Error::RandomUnavailable => {
+                write!(f, "could not read from the random source")
+            }
+            Error::AuditRecordMissing => write!(f, "audit log is missing a record"),
+        }
+    }
+}
+
+// Name of a table qualified by its database, if any.
+fn qualified(db: &Option<String>, table: &str) -> String {
+    match db {
+        Some(db) => format!("{}.{}", db, table),
+        None => table.to_string(),
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::KvsError(err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 impl From<kvs::Error> for Error {
@@ -16,3 +152,52 @@ impl From<kvs::Error> for Error {
         Error::KvsError(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode;
+    use std::error::Error as StdError;
+
+    #[test]
+    fn test_code_and_message() {
+        let err = Error::TableDoesNotExist {
+            db: Some("testdb".to_string()),
+            table: "foo".to_string(),
+        };
+        assert_eq!(err.code(), "42P01");
+        assert_eq!(err.to_string(), "table \"testdb.foo\" does not exist");
+
+        let err = Error::ColumnDoesNotExist {
+            db: None,
+            table: "tmp".to_string(),
+            column: "x".to_string(),
+        };
+        assert_eq!(err.code(), "42703");
+        assert_eq!(
+            err.to_string(),
+            "column \"x\" of table \"tmp\" does not exist"
+        );
+
+        let err = Error::from(kvs::Error::WriteWriteConflict);
+        assert_eq!(err.code(), "40001");
+        assert!(err.is_retryable());
+
+        let err = Error::from(kvs::Error::InvalidTxnId);
+        assert_eq!(err.code(), kvs::Error::InvalidTxnId.code());
+        assert!(!err.is_retryable());
+    }
+
+    #[test]
+    fn test_source_chain() {
+        let err = Error::from(kvs::Error::from(encode::Error::NotEnoughBytes));
+        assert_eq!(err.code(), "XX001");
+        assert_eq!(err.to_string(), "storage error");
+
+        let kvs_err = err.source().expect("Missing kvs error");
+        assert_eq!(kvs_err.to_string(), "could not encode or decode value");
+        let encode_err = kvs_err.source().expect("Missing encode error");
+        assert_eq!(encode_err.to_string(), "not enough bytes to decode value");
+        assert!(encode_err.source().is_none());
+    }
+}
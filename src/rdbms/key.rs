@@ -3,13 +3,32 @@ use crate::kvs;
 #[derive(Hash, Eq, PartialEq, Clone, Copy)]
 pub enum KeySpace {
     Catalog,
+    Audit,
 }
 
 impl kvs::KeySpaceId for KeySpace {}
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Hash, Eq, PartialEq, Clone)]
 pub enum Key {
     SystemMeta,
+    UserMeta {
+        user: String,
+    },
+    GrantMeta {
+        user: String,
+    },
+    AuditLogMeta {
+        db: String,
+    },
+    AuditRecord {
+        db: String,
+        seq: u64,
+    },
+    SystemAuditLogMeta,
+    SystemAuditRecord {
+        seq: u64,
+    },
     DatabaseMeta {
         db: String,
     },
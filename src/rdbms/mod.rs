@@ -1,8 +1,25 @@
+mod audit;
+mod cancel;
 mod catalog;
+mod collation;
 mod datatype;
+mod dump;
 mod error;
 mod key;
+mod notify;
+mod rowcodec;
+mod session;
 
-pub use catalog::{Catalog, ColumnMeta, DatabaseMeta, SystemMeta, TableMeta};
+pub use audit::{AuditLog, AuditRecord};
+pub use cancel::CancelHandle;
+pub use catalog::{
+    Catalog, ColumnMeta, DatabaseMeta, GrantMeta, Privilege, RandomSource, SystemMeta, TableMeta,
+    UserMeta,
+};
+pub use collation::Collation;
 pub use datatype::DataType;
+pub use dump::dump_sql;
 pub use error::Error;
+pub use notify::{Notification, NotificationHub};
+pub use rowcodec::{LayoutColumn, RowCodec, RowLayout, Value};
+pub use session::{Session, SessionSettings};
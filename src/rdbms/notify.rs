@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+/// A message sent on a channel by `Session::notify()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub channel: String,
+    pub payload: String,
+}
+
+/// Routes notifications from the sessions that send them to the
+/// sessions listening on each channel.  Shared by every session
+/// that should see the others' notifications.
+pub struct NotificationHub {
+    listeners: Mutex<HashMap<String, Vec<Sender<Notification>>>>,
+}
+
+impl NotificationHub {
+    pub fn new() -> NotificationHub {
+        NotificationHub {
+            listeners: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Receive notifications sent on `channel` from now on.
+    /// Dropping the receiver stops listening.
+    pub fn listen(&self, channel_name: &str) -> Receiver<Notification> {
+        let (sender, receiver) = channel();
+        self.listeners
+            .lock()
+            .expect("Could not acquire lock on listeners")
+            .entry(channel_name.to_string())
+            .or_default()
+            .push(sender);
+        receiver
+    }
+
+    /// Deliver notifications to every listener on their channels, in order.
+    pub fn publish<I>(&self, notifications: I)
+    where
+        I: IntoIterator<Item = Notification>,
+    {
+        let mut listeners = self
+            .listeners
+            .lock()
+            .expect("Could not acquire lock on listeners");
+        for notification in notifications {
+            if let Some(senders) = listeners.get_mut(&notification.channel) {
+                senders.retain(|sender| sender.send(notification.clone()).is_ok());
+                if senders.is_empty() {
+                    listeners.remove(&notification.channel);
+                }
+            }
+        }
+    }
+}
+
+impl Default for NotificationHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(channel: &str, payload: &str) -> Notification {
+        Notification {
+            channel: channel.to_string(),
+            payload: payload.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_publish_to_listeners_on_channel() {
+        let hub = NotificationHub::new();
+        let foo1 = hub.listen("foo");
+        let foo2 = hub.listen("foo");
+        let bar = hub.listen("bar");
+
+        hub.publish(vec![notification("foo", "a"), notification("foo", "b")]);
+
+        for receiver in [foo1, foo2].iter() {
+            let received: Vec<Notification> = receiver.try_iter().collect();
+            assert_eq!(
+                received,
+                vec![notification("foo", "a"), notification("foo", "b")]
+            );
+        }
+        assert!(bar.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_dropped_listener_is_removed() {
+        let hub = NotificationHub::new();
+        drop(hub.listen("foo"));
+        hub.publish(vec![notification("foo", "a")]);
+        assert!(hub
+            .listeners
+            .lock()
+            .expect("Could not acquire lock on listeners")
+            .is_empty());
+    }
+}
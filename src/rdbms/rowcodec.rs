@@ -0,0 +1,407 @@
+use crate::encode::{self, BytesReader, BytesWriter, Decode, Encode};
+use crate::rdbms::DataType;
+use std::convert::TryFrom;
+
+// Rows are encoded as:
+//
+//   layout version (u32)
+//   null bitmap, one bit per column, set if the column is null
+//   fixed region, one 8-byte slot per column:
+//     Int64: the value
+//     Text: offset (u32) and length (u32) of its bytes in the tail
+//     null: zeroes
+//   variable-width tail holding text bytes
+//
+// Every slot has the same width, so any column can be read without
+// decoding the columns before it.
+const VERSION_LEN: usize = 4;
+const SLOT_LEN: usize = 8;
+
+/// A single column value of a row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Null,
+    Int64(i64),
+    Text(String),
+}
+
+impl Value {
+    fn matches(&self, data_type: DataType) -> bool {
+        matches!(
+            (self, data_type),
+            (Value::Null, _)
+                | (Value::Int64(_), DataType::Int64)
+                | (Value::Text(_), DataType::Text)
+        )
+    }
+}
+
+/// A column of a row layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutColumn {
+    /// Identifies the column across layouts.  Ids are never reused,
+    /// so a column dropped and re-added with the same name gets a new id.
+    pub id: u32,
+    pub name: String,
+    pub data_type: DataType,
+}
+
+impl LayoutColumn {
+    pub fn new(id: u32, name: &str, data_type: DataType) -> LayoutColumn {
+        LayoutColumn {
+            id,
+            name: name.to_string(),
+            data_type,
+        }
+    }
+}
+
+/// Columns of a table as of one schema version, in the order they are encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowLayout {
+    version: u32,
+    columns: Vec<LayoutColumn>,
+}
+
+impl RowLayout {
+    pub fn new(version: u32, columns: Vec<LayoutColumn>) -> RowLayout {
+        RowLayout { version, columns }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn columns(&self) -> &[LayoutColumn] {
+        &self.columns
+    }
+}
+
+/// Encodes rows using the newest layout of a table, and decodes rows
+/// written with any earlier layout into the newest one.  Columns are matched
+/// by id, so columns added since a row was written decode as null and
+/// dropped columns are skipped.  Rows never need to be rewritten when
+/// a layout is added.
+pub struct RowCodec {
+    // Ordered oldest first, so the last layout is the current one.
+    layouts: Vec<RowLayout>,
+}
+
+impl RowCodec {
+    /// Create a codec from every layout the table has had.
+    /// Panics if `layouts` is empty or two layouts have the same version.
+    pub fn new(mut layouts: Vec<RowLayout>) -> RowCodec {
+        assert!(
+            !layouts.is_empty(),
+            "Row codec requires at least one layout"
+        );
+        layouts.sort_by_key(|layout| layout.version);
+        assert!(
+            layouts.windows(2).all(|w| w[0].version != w[1].version),
+            "Row codec layouts must have distinct versions"
+        );
+        RowCodec { layouts }
+    }
+
+    pub fn current_layout(&self) -> &RowLayout {
+        self.layouts.last().expect("Row codec has no layouts")
+    }
+
+    /// Encode `row`, which must have one value per column of the current layout.
+    pub fn encode_row(&self, row: &[Value]) -> Result<Vec<u8>, encode::Error> {
+        let layout = self.current_layout();
+        if row.len() != layout.columns.len() {
+            return Err(encode::Error::InvalidFormat(
+                "Row does not have one value per column",
+            ));
+        }
+
+        let mut bitmap = vec![0u8; bitmap_len(row.len())];
+        let mut fixed = Vec::with_capacity(row.len() * SLOT_LEN);
+        let mut tail = Vec::new();
+        for (idx, (val, column)) in row.iter().zip(layout.columns.iter()).enumerate() {
+            if !val.matches(column.data_type) {
+                return Err(encode::Error::InvalidFormat(
+                    "Value does not match column type",
+                ));
+            }
+            match val {
+                Value::Null => {
+                    bitmap[idx / 8] |= 1 << (idx % 8);
+                    fixed.extend_from_slice(&[0; SLOT_LEN]);
+                }
+                Value::Int64(n) => fixed.extend_from_slice(&n.to_le_bytes()),
+                Value::Text(s) => {
+                    let offset = u32::try_from(tail.len())
+                        .map_err(|_| encode::Error::InvalidFormat("Row is too large"))?;
+                    let len = u32::try_from(s.len())
+                        .map_err(|_| encode::Error::InvalidFormat("Text value is too large"))?;
+                    fixed.extend_from_slice(&offset.to_le_bytes());
+                    fixed.extend_from_slice(&len.to_le_bytes());
+                    tail.extend_from_slice(s.as_bytes());
+                }
+            }
+        }
+
+        let mut buf = Vec::with_capacity(VERSION_LEN + bitmap.len() + fixed.len() + tail.len());
+        let mut w = BytesWriter::new(&mut buf);
+        layout.version.encode(&mut w);
+        w.write(&bitmap);
+        w.write(&fixed);
+        w.write(&tail);
+        Ok(buf)
+    }
+
+    /// Decode a row written with any known layout into the current layout.
+    pub fn decode_row(&self, bytes: &[u8]) -> Result<Vec<Value>, encode::Error> {
+        let view = self.view(bytes)?;
+        (0..self.current_layout().columns.len())
+            .map(|idx| view.column(self.current_layout(), idx))
+            .collect()
+    }
+
+    /// Decode only the column at `idx` in the current layout,
+    /// without decoding the rest of the row.
+    pub fn decode_column(&self, bytes: &[u8], idx: usize) -> Result<Value, encode::Error> {
+        if idx >= self.current_layout().columns.len() {
+            return Err(encode::Error::InvalidFormat("Column index out of range"));
+        }
+        self.view(bytes)?.column(self.current_layout(), idx)
+    }
+
+    fn view<'a>(&'a self, bytes: &'a [u8]) -> Result<RowView<'a>, encode::Error> {
+        let version = u32::decode(&mut BytesReader::new(bytes))?;
+        let layout = self
+            .layouts
+            .iter()
+            .find(|layout| layout.version == version)
+            .ok_or(encode::Error::InvalidFormat(
+                "Unrecognized row layout version",
+            ))?;
+        let num_columns = layout.columns.len();
+        let fixed_start = VERSION_LEN + bitmap_len(num_columns);
+        let tail_start = fixed_start + num_columns * SLOT_LEN;
+        if bytes.len() < tail_start {
+            return Err(encode::Error::NotEnoughBytes);
+        }
+        Ok(RowView {
+            layout,
+            bytes,
+            fixed_start,
+            tail_start,
+        })
+    }
+}
+
+fn bitmap_len(num_columns: usize) -> usize {
+    num_columns.div_ceil(8)
+}
+
+/// A row encoded with `layout`, read in place.
+struct RowView<'a> {
+    layout: &'a RowLayout,
+    bytes: &'a [u8],
+    fixed_start: usize,
+    tail_start: usize,
+}
+
+impl<'a> RowView<'a> {
+    /// Value of the column at `idx` in `current`, matched by id
+    /// to the layout the row was written with.
+    fn column(&self, current: &RowLayout, idx: usize) -> Result<Value, encode::Error> {
+        let column = &current.columns[idx];
+        if self.layout.version == current.version {
+            return self.stored_value(idx);
+        }
+        match self.layout.columns.iter().position(|c| c.id == column.id) {
+            None => Ok(Value::Null),
+            Some(stored_idx) if self.layout.columns[stored_idx].data_type == column.data_type => {
+                self.stored_value(stored_idx)
+            }
+            Some(_) => Err(encode::Error::InvalidFormat(
+                "Column type changed since row was written",
+            )),
+        }
+    }
+
+    fn stored_value(&self, idx: usize) -> Result<Value, encode::Error> {
+        if self.bytes[VERSION_LEN + idx / 8] & (1 << (idx % 8)) != 0 {
+            return Ok(Value::Null);
+        }
+        let slot_start = self.fixed_start + idx * SLOT_LEN;
+        let mut r = BytesReader::new(&self.bytes[slot_start..slot_start + SLOT_LEN]);
+        match self.layout.columns[idx].data_type {
+            DataType::Int64 => Ok(Value::Int64(i64::decode(&mut r)?)),
+            DataType::Text => {
+                let offset = u32::decode(&mut r)? as usize;
+                let len = u32::decode(&mut r)? as usize;
+                let start = self.tail_start + offset;
+                let text_bytes = self
+                    .bytes
+                    .get(start..start + len)
+                    .ok_or(encode::Error::NotEnoughBytes)?;
+                let text = std::str::from_utf8(text_bytes)
+                    .map_err(|_| encode::Error::InvalidFormat("Invalid UTF8 string bytes"))?;
+                Ok(Value::Text(text.to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(version: u32, columns: &[(u32, &str, DataType)]) -> RowLayout {
+        RowLayout::new(
+            version,
+            columns
+                .iter()
+                .map(|(id, name, data_type)| LayoutColumn::new(*id, name, *data_type))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_encode_and_decode_current_layout() {
+        let codec = RowCodec::new(vec![layout(
+            1,
+            &[(0, "id", DataType::Int64), (1, "name", DataType::Text)],
+        )]);
+        for row in [
+            vec![Value::Int64(-7), Value::Text("foo".to_string())],
+            vec![Value::Int64(1), Value::Null],
+        ]
+        .iter()
+        {
+            let bytes = codec.encode_row(row).expect("Could not encode row");
+            assert_eq!(codec.decode_row(&bytes), Ok(row.clone()));
+        }
+    }
+
+    #[test]
+    fn test_compact_encoding() {
+        let codec = RowCodec::new(vec![layout(
+            1,
+            &[
+                (0, "a", DataType::Int64),
+                (1, "b", DataType::Text),
+                (2, "c", DataType::Int64),
+            ],
+        )]);
+        let bytes = codec
+            .encode_row(&[Value::Null, Value::Text("xy".to_string()), Value::Int64(5)])
+            .expect("Could not encode row");
+        let mut expected = vec![1, 0, 0, 0, 0b001];
+        expected.extend_from_slice(&[0; 8]);
+        expected.extend_from_slice(&[0, 0, 0, 0, 2, 0, 0, 0]);
+        expected.extend_from_slice(&5i64.to_le_bytes());
+        expected.extend_from_slice(b"xy");
+        assert_eq!(bytes, expected);
+
+        assert_eq!(codec.decode_column(&bytes, 0), Ok(Value::Null));
+        assert_eq!(
+            codec.decode_column(&bytes, 1),
+            Ok(Value::Text("xy".to_string()))
+        );
+        assert_eq!(codec.decode_column(&bytes, 2), Ok(Value::Int64(5)));
+        assert!(codec.decode_column(&bytes, 3).is_err());
+        assert_eq!(
+            codec.decode_row(&bytes[..bytes.len() - 1]),
+            Err(encode::Error::NotEnoughBytes)
+        );
+    }
+
+    #[test]
+    fn test_many_columns() {
+        let columns: Vec<LayoutColumn> = (0..20)
+            .map(|i| LayoutColumn::new(i, &format!("c{}", i), DataType::Int64))
+            .collect();
+        let codec = RowCodec::new(vec![RowLayout::new(1, columns)]);
+        let row: Vec<Value> = (0..20i64)
+            .map(|i| {
+                if i % 3 == 0 {
+                    Value::Null
+                } else {
+                    Value::Int64(i)
+                }
+            })
+            .collect();
+        let bytes = codec.encode_row(&row).expect("Could not encode row");
+        assert_eq!(bytes.len(), 4 + 3 + 20 * 8);
+        assert_eq!(codec.decode_row(&bytes), Ok(row));
+    }
+
+    #[test]
+    fn test_decode_older_layout() {
+        let v1 = layout(1, &[(0, "id", DataType::Int64), (1, "old", DataType::Text)]);
+        let v2 = layout(2, &[(0, "id", DataType::Int64), (2, "new", DataType::Text)]);
+        let old_codec = RowCodec::new(vec![v1.clone()]);
+        let bytes = old_codec
+            .encode_row(&[Value::Int64(3), Value::Text("dropped".to_string())])
+            .expect("Could not encode row");
+
+        let codec = RowCodec::new(vec![v2, v1]);
+        assert_eq!(
+            codec.decode_row(&bytes),
+            Ok(vec![Value::Int64(3), Value::Null])
+        );
+    }
+
+    #[test]
+    fn test_decode_readded_column() {
+        let v1 = layout(
+            1,
+            &[(0, "id", DataType::Int64), (1, "name", DataType::Text)],
+        );
+        let v2 = layout(2, &[(0, "id", DataType::Int64)]);
+        let v3 = layout(
+            3,
+            &[(0, "id", DataType::Int64), (2, "name", DataType::Text)],
+        );
+        let bytes = RowCodec::new(vec![v1.clone()])
+            .encode_row(&[Value::Int64(3), Value::Text("dropped".to_string())])
+            .expect("Could not encode row");
+        assert_eq!(
+            RowCodec::new(vec![v1, v2, v3]).decode_row(&bytes),
+            Ok(vec![Value::Int64(3), Value::Null])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Row codec layouts must have distinct versions")]
+    fn test_duplicate_layout_versions() {
+        RowCodec::new(vec![
+            layout(1, &[(0, "id", DataType::Int64)]),
+            layout(1, &[(1, "name", DataType::Text)]),
+        ]);
+    }
+
+    #[test]
+    fn test_decode_changed_column_type() {
+        let v1 = layout(1, &[(0, "id", DataType::Int64)]);
+        let v2 = layout(2, &[(0, "id", DataType::Text)]);
+        let bytes = RowCodec::new(vec![v1.clone()])
+            .encode_row(&[Value::Int64(3)])
+            .expect("Could not encode row");
+        assert_eq!(
+            RowCodec::new(vec![v1, v2]).decode_row(&bytes),
+            Err(encode::Error::InvalidFormat(
+                "Column type changed since row was written"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_encode_mismatched_row() {
+        let codec = RowCodec::new(vec![layout(1, &[(0, "id", DataType::Int64)])]);
+        assert!(codec.encode_row(&[]).is_err());
+        assert!(codec.encode_row(&[Value::Text("foo".to_string())]).is_err());
+        assert_eq!(
+            codec.decode_row(&[9, 0, 0, 0]),
+            Err(encode::Error::InvalidFormat(
+                "Unrecognized row layout version"
+            ))
+        );
+    }
+}
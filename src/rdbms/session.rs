@@ -0,0 +1,1410 @@
+use crate::kvs::Store;
+use crate::kvs::TxnId;
+use crate::kvs::VacuumStats;
+use crate::rdbms::audit::{AuditLog, AuditRecord};
+use crate::rdbms::cancel::CancelHandle;
+use crate::rdbms::catalog::{Catalog, ColumnMeta, GrantMeta, Privilege, RandomSource, TableMeta};
+use crate::rdbms::error::Error;
+use crate::rdbms::key::{Key, KeySpace};
+use crate::rdbms::notify::{Notification, NotificationHub};
+use crate::rdbms::DataType;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+const DEFAULT_SORT_MEM_BUDGET: usize = 4 * 1024 * 1024;
+
+/// Per-session configuration, initialized to defaults when the session starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionSettings {
+    /// Maximum bytes a single sort may buffer in memory.
+    pub sort_mem_budget: usize,
+
+    /// Maximum time a statement may run before it is aborted, if any.
+    /// Must be None on wasm32-unknown-unknown, which has no monotonic clock.
+    pub statement_timeout: Option<Duration>,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        SessionSettings {
+            sort_mem_budget: DEFAULT_SORT_MEM_BUDGET,
+            statement_timeout: None,
+        }
+    }
+}
+
+/// A table visible only to the session that created it.
+/// Temporary tables live in session memory and are discarded with the session.
+struct TempTable {
+    meta: TableMeta,
+    columns: HashMap<String, ColumnMeta>,
+}
+
+/// A client's connection to the database.
+/// The session owns at most one explicit transaction at a time,
+/// started by `begin()` and ended by `commit()` or `rollback()`.
+/// Statements executed outside an explicit transaction run in their
+/// own transaction that commits immediately.
+pub struct Session<'a> {
+    store: &'a Store<KeySpace, Key>,
+    catalog: Catalog<'a>,
+    audit_log: AuditLog<'a>,
+    txn_id: Cell<Option<TxnId>>,
+    cancel_handle: CancelHandle,
+    deadline: Cell<Option<Instant>>,
+
+    // Number of `execute()` calls on the stack, since statements such as
+    // `create_table()` call it while checking privileges.
+    execute_depth: Cell<usize>,
+    current_db: Option<String>,
+    user: Option<String>,
+    settings: SessionSettings,
+    temp_tables: HashMap<String, TempTable>,
+    notification_hub: Option<&'a NotificationHub>,
+    pending_notifications: RefCell<Vec<Notification>>,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(store: &'a Store<KeySpace, Key>) -> Session<'a> {
+        Session {
+            store,
+            catalog: Catalog::new(store),
+            audit_log: AuditLog::new(store),
+            txn_id: Cell::new(None),
+            cancel_handle: CancelHandle::new(),
+            deadline: Cell::new(None),
+            execute_depth: Cell::new(0),
+            current_db: None,
+            user: None,
+            settings: SessionSettings::default(),
+            temp_tables: HashMap::new(),
+            notification_hub: None,
+            pending_notifications: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn audit_log(&self) -> &AuditLog<'a> {
+        &self.audit_log
+    }
+
+    pub fn settings(&self) -> &SessionSettings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut SessionSettings {
+        &mut self.settings
+    }
+
+    pub fn current_database(&self) -> Option<&str> {
+        self.current_db.as_deref()
+    }
+
+    /// The authenticated user, or None for an embedded session
+    /// that is not subject to access control.
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// Authenticate as a user.  Once authenticated, the session may only
+    /// access objects on which the user has been granted privileges.
+    pub fn authenticate(&mut self, user_name: &str, password: &str) -> Result<(), Error> {
+        let catalog = &self.catalog;
+        self.execute(|txn_id| catalog.authenticate(txn_id, user_name, password))?;
+        self.user = Some(user_name.to_string());
+        Ok(())
+    }
+
+    /// Check that the session may perform an operation before it touches storage.
+    pub fn check_privilege(
+        &self,
+        privilege: Privilege,
+        db_name: &str,
+        tbl_name: Option<&str>,
+    ) -> Result<(), Error> {
+        match &self.user {
+            None => Ok(()),
+            Some(_) => {
+                self.execute(|txn_id| self.check_privilege_in(txn_id, privilege, db_name, tbl_name))
+            }
+        }
+    }
+
+    // Check a privilege in the txn of the statement that needs it,
+    // so a concurrent revoke cannot take effect between the check and the statement.
+    fn check_privilege_in(
+        &self,
+        txn_id: TxnId,
+        privilege: Privilege,
+        db_name: &str,
+        tbl_name: Option<&str>,
+    ) -> Result<(), Error> {
+        match &self.user {
+            None => Ok(()),
+            Some(user_name) => self
+                .catalog
+                .check_privilege(txn_id, user_name, privilege, db_name, tbl_name),
+        }
+    }
+
+    // Fail with `PermissionDenied` unless the session is embedded, for
+    // statements that affect every database, since privileges are granted
+    // per database.
+    fn check_embedded(&self) -> Result<(), Error> {
+        match &self.user {
+            None => Ok(()),
+            Some(_) => Err(Error::PermissionDenied),
+        }
+    }
+
+    // Privileges of the authenticated user, or None for an embedded session.
+    fn grants_in(&self, txn_id: TxnId) -> Result<Option<GrantMeta>, Error> {
+        self.user
+            .as_ref()
+            .map(|user_name| self.catalog.get_grant_meta(txn_id, user_name))
+            .transpose()
+    }
+
+    // Fail with `PermissionDenied` unless the session may see the table,
+    // or the database if `tbl_name` is None.  Checked before the object is
+    // looked up, so users cannot probe for objects they have no access to.
+    fn check_visible_in(
+        &self,
+        txn_id: TxnId,
+        db_name: &str,
+        tbl_name: Option<&str>,
+    ) -> Result<(), Error> {
+        match self.grants_in(txn_id)? {
+            Some(grants) if !grants.has_any_privilege(db_name, tbl_name) => {
+                Err(Error::PermissionDenied)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Set the database used to resolve unqualified table names.
+    pub fn use_database(&mut self, db_name: &str) -> Result<(), Error> {
+        self.execute(|txn_id| {
+            self.check_visible_in(txn_id, db_name, None)?;
+            self.catalog.get_database_meta(txn_id, db_name)
+        })?;
+        self.current_db = Some(db_name.to_string());
+        Ok(())
+    }
+
+    /// Resolve the database for a statement, preferring an explicit name
+    /// and falling back to the session's current database.
+    pub fn resolve_database<'b>(&'b self, db_name: Option<&'b str>) -> Result<&'b str, Error> {
+        db_name
+            .or_else(|| self.current_database())
+            .ok_or(Error::NoDatabaseSelected)
+    }
+
+    /// Create a table in a database, checking privileges and recording
+    /// the statement in the database's audit log if auditing is enabled.
+    pub fn create_table(&self, db_name: Option<&str>, tbl_name: &str) -> Result<(), Error> {
+        let db_name = self.resolve_database(db_name)?;
+        self.execute(|txn_id| {
+            self.check_privilege_in(txn_id, Privilege::Ddl, db_name, None)?;
+            self.catalog.create_table(txn_id, db_name, tbl_name)?;
+            self.audit(txn_id, db_name, "CREATE TABLE", tbl_name)
+        })
+    }
+
+    /// Create a column in a table, checking privileges and recording
+    /// the statement in the database's audit log if auditing is enabled.
+    pub fn create_column(
+        &self,
+        db_name: Option<&str>,
+        tbl_name: &str,
+        col_name: &str,
+        data_type: DataType,
+    ) -> Result<(), Error> {
+        let db_name = self.resolve_database(db_name)?;
+        self.execute(|txn_id| {
+            self.check_privilege_in(txn_id, Privilege::Ddl, db_name, Some(tbl_name))?;
+            self.catalog
+                .create_column(txn_id, db_name, tbl_name, col_name, data_type)?;
+            self.audit(
+                txn_id,
+                db_name,
+                "ALTER TABLE ADD COLUMN",
+                &format!("{}.{}", tbl_name, col_name),
+            )
+        })
+    }
+
+    /// Create a database, recording the statement in the system audit log.
+    /// Only embedded sessions may create databases.
+    pub fn create_database(&self, db_name: &str) -> Result<(), Error> {
+        self.check_embedded()?;
+        self.execute(|txn_id| {
+            self.catalog.create_database(txn_id, db_name)?;
+            self.audit_system(txn_id, "CREATE DATABASE", db_name)
+        })
+    }
+
+    /// Create a user, recording the statement in the system audit log.
+    /// Only embedded sessions may manage users.
+    pub fn create_user(&self, user_name: &str, password: &str) -> Result<(), Error> {
+        self.check_embedded()?;
+        self.execute(|txn_id| {
+            self.catalog.create_user(txn_id, user_name, password)?;
+            self.audit_system(txn_id, "CREATE USER", user_name)
+        })
+    }
+
+    /// Change a user's password, recording the statement in the system audit
+    /// log.  Authenticated users may change only their own password.
+    pub fn alter_user_password(&self, user_name: &str, password: &str) -> Result<(), Error> {
+        if self.user.as_deref().is_some_and(|user| user != user_name) {
+            return Err(Error::PermissionDenied);
+        }
+        self.execute(|txn_id| {
+            self.catalog
+                .alter_user_password(txn_id, user_name, password)?;
+            self.audit_system(txn_id, "ALTER USER PASSWORD", user_name)
+        })
+    }
+
+    /// Grant a privilege on a table, or on a whole database if `tbl_name`
+    /// is None.  Requires the DDL privilege on the database, and records
+    /// the statement in the database's audit log if auditing is enabled.
+    pub fn grant(
+        &self,
+        user_name: &str,
+        privilege: Privilege,
+        db_name: &str,
+        tbl_name: Option<&str>,
+    ) -> Result<(), Error> {
+        self.execute(|txn_id| {
+            self.check_privilege_in(txn_id, Privilege::Ddl, db_name, None)?;
+            self.catalog
+                .grant(txn_id, user_name, privilege, db_name, tbl_name)?;
+            let object = Self::grant_object(privilege, db_name, tbl_name, "TO", user_name);
+            self.audit(txn_id, db_name, "GRANT", &object)
+        })
+    }
+
+    /// Revoke a privilege granted by `grant()`.
+    /// Requires the DDL privilege on the database, and records the
+    /// statement in the database's audit log if auditing is enabled.
+    pub fn revoke(
+        &self,
+        user_name: &str,
+        privilege: Privilege,
+        db_name: &str,
+        tbl_name: Option<&str>,
+    ) -> Result<(), Error> {
+        self.execute(|txn_id| {
+            self.check_privilege_in(txn_id, Privilege::Ddl, db_name, None)?;
+            self.catalog
+                .revoke(txn_id, user_name, privilege, db_name, tbl_name)?;
+            let object = Self::grant_object(privilege, db_name, tbl_name, "FROM", user_name);
+            self.audit(txn_id, db_name, "REVOKE", &object)
+        })
+    }
+
+    // Describe a grant for the audit log, such as "SELECT ON db.tbl TO user".
+    fn grant_object(
+        privilege: Privilege,
+        db_name: &str,
+        tbl_name: Option<&str>,
+        preposition: &str,
+        user_name: &str,
+    ) -> String {
+        match tbl_name {
+            None => format!("{} ON {} {} {}", privilege, db_name, preposition, user_name),
+            Some(tbl_name) => format!(
+                "{} ON {}.{} {} {}",
+                privilege, db_name, tbl_name, preposition, user_name
+            ),
+        }
+    }
+
+    /// Enable or disable the audit log of a database.
+    /// Requires the DDL privilege on the database.  The change itself is
+    /// always recorded, so disabling auditing leaves a trace.
+    pub fn set_audit_enabled(&self, db_name: &str, enabled: bool) -> Result<(), Error> {
+        self.execute(|txn_id| {
+            self.check_privilege_in(txn_id, Privilege::Ddl, db_name, None)?;
+            self.catalog.set_audit_enabled(txn_id, db_name, enabled)?;
+            let action = if enabled {
+                "ALTER DATABASE SET AUDIT ON"
+            } else {
+                "ALTER DATABASE SET AUDIT OFF"
+            };
+            let record = self.audit_record(txn_id, action, db_name);
+            self.audit_log.append(txn_id, db_name, &record)
+        })
+    }
+
+    fn audit(&self, txn_id: TxnId, db_name: &str, action: &str, object: &str) -> Result<(), Error> {
+        let db_meta = self.catalog.get_database_meta(txn_id, db_name)?;
+        if !db_meta.audit_enabled() {
+            return Ok(());
+        }
+        let record = self.audit_record(txn_id, action, object);
+        self.audit_log.append(txn_id, db_name, &record)
+    }
+
+    // Statements that affect every database are always audited.
+    fn audit_system(&self, txn_id: TxnId, action: &str, object: &str) -> Result<(), Error> {
+        let record = self.audit_record(txn_id, action, object);
+        self.audit_log.append_system(txn_id, &record)
+    }
+
+    fn audit_record(&self, txn_id: TxnId, action: &str, object: &str) -> AuditRecord {
+        AuditRecord {
+            txn_id,
+            user: self.user.clone(),
+            action: action.to_string(),
+            object: object.to_string(),
+        }
+    }
+
+    /// List the databases the session can see, as for `SHOW DATABASES`.
+    pub fn show_databases(&self) -> Result<Vec<String>, Error> {
+        self.execute(|txn_id| {
+            let grants = self.grants_in(txn_id)?;
+            let system_meta = self.catalog.get_system_meta(txn_id)?;
+            Ok(system_meta
+                .iter_db_names()
+                .filter(|db_name| {
+                    grants
+                        .as_ref()
+                        .is_none_or(|grants| grants.has_any_privilege(db_name, None))
+                })
+                .cloned()
+                .collect())
+        })
+    }
+
+    /// List the tables the session can see in a database, as for `SHOW TABLES`.
+    pub fn show_tables(&self, db_name: Option<&str>) -> Result<Vec<String>, Error> {
+        let db_name = self.resolve_database(db_name)?;
+        self.execute(|txn_id| {
+            self.check_visible_in(txn_id, db_name, None)?;
+            let grants = self.grants_in(txn_id)?;
+            let db_meta = self.catalog.get_database_meta(txn_id, db_name)?;
+            Ok(db_meta
+                .iter_tbl_names()
+                .filter(|tbl_name| {
+                    grants
+                        .as_ref()
+                        .is_none_or(|grants| grants.has_any_privilege(db_name, Some(tbl_name)))
+                })
+                .cloned()
+                .collect())
+        })
+    }
+
+    /// List the columns of a table with their metadata, as for `DESCRIBE`.
+    pub fn describe_table(
+        &self,
+        db_name: Option<&str>,
+        tbl_name: &str,
+    ) -> Result<Vec<(String, ColumnMeta)>, Error> {
+        let db_name = self.resolve_database(db_name)?;
+        self.execute(|txn_id| {
+            self.check_visible_in(txn_id, db_name, Some(tbl_name))?;
+            let tbl_meta = self.catalog.get_table_meta(txn_id, db_name, tbl_name)?;
+            tbl_meta
+                .iter_col_names()
+                .map(|col_name| {
+                    self.catalog
+                        .get_column_meta(txn_id, db_name, tbl_name, col_name)
+                        .map(|col_meta| (col_name.to_string(), col_meta))
+                })
+                .collect()
+        })
+    }
+
+    /// Unlink old versions of catalog and audit records, as for `VACUUM`.
+    /// Versions still visible to an open transaction are kept.
+    /// Only embedded sessions may vacuum, since the catalog and audit
+    /// records of every database share the same keyspaces.
+    pub fn vacuum(&self) -> Result<VacuumStats, Error> {
+        self.check_embedded()?;
+        let mut stats = VacuumStats::default();
+        for keyspace in [KeySpace::Catalog, KeySpace::Audit].iter() {
+            stats.add(self.store.vacuum(*keyspace)?);
+        }
+        Ok(stats)
+    }
+
+    pub fn create_temp_table(&mut self, tbl_name: &str) -> Result<(), Error> {
+        if self.temp_tables.contains_key(tbl_name) {
+            return Err(Error::TableAlreadyExists {
+                db: None,
+                table: tbl_name.to_string(),
+            });
+        }
+        self.temp_tables.insert(
+            tbl_name.to_string(),
+            TempTable {
+                meta: TableMeta::new(),
+                columns: HashMap::new(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn create_temp_column(
+        &mut self,
+        tbl_name: &str,
+        col_name: &str,
+        data_type: DataType,
+    ) -> Result<(), Error> {
+        let tbl = self
+            .temp_tables
+            .get_mut(tbl_name)
+            .ok_or_else(|| Error::TableDoesNotExist {
+                db: None,
+                table: tbl_name.to_string(),
+            })?;
+        if tbl.columns.contains_key(col_name) {
+            return Err(Error::ColumnAlreadyExists {
+                db: None,
+                table: tbl_name.to_string(),
+                column: col_name.to_string(),
+            });
+        }
+        tbl.columns
+            .insert(col_name.to_string(), ColumnMeta::new(data_type));
+        tbl.meta.insert_col_name(col_name);
+        Ok(())
+    }
+
+    pub fn get_temp_table_meta(&self, tbl_name: &str) -> Result<&TableMeta, Error> {
+        self.temp_tables
+            .get(tbl_name)
+            .map(|tbl| &tbl.meta)
+            .ok_or_else(|| Error::TableDoesNotExist {
+                db: None,
+                table: tbl_name.to_string(),
+            })
+    }
+
+    pub fn get_temp_column_meta(
+        &self,
+        tbl_name: &str,
+        col_name: &str,
+    ) -> Result<&ColumnMeta, Error> {
+        self.temp_tables
+            .get(tbl_name)
+            .ok_or_else(|| Error::TableDoesNotExist {
+                db: None,
+                table: tbl_name.to_string(),
+            })?
+            .columns
+            .get(col_name)
+            .ok_or_else(|| Error::ColumnDoesNotExist {
+                db: None,
+                table: tbl_name.to_string(),
+                column: col_name.to_string(),
+            })
+    }
+
+    pub fn drop_temp_table(&mut self, tbl_name: &str) -> Result<(), Error> {
+        self.temp_tables
+            .remove(tbl_name)
+            .map(|_| ())
+            .ok_or_else(|| Error::TableDoesNotExist {
+                db: None,
+                table: tbl_name.to_string(),
+            })
+    }
+
+    /// Salt password hashes with bytes from `random_source`.  Required on
+    /// targets other than unix, where there is no default random source.
+    pub fn set_random_source(&mut self, random_source: RandomSource) {
+        self.catalog.set_random_source(random_source);
+    }
+
+    /// Send and receive notifications through `hub`.
+    pub fn set_notification_hub(&mut self, hub: &'a NotificationHub) {
+        self.notification_hub = Some(hub);
+    }
+
+    /// Receive notifications sent on `channel_name` by sessions sharing
+    /// the notification hub, including this one.
+    pub fn listen(&self, channel_name: &str) -> Result<Receiver<Notification>, Error> {
+        self.notification_hub
+            .map(|hub| hub.listen(channel_name))
+            .ok_or(Error::NotificationsUnavailable)
+    }
+
+    /// Send a notification on `channel_name`.  Inside an explicit transaction,
+    /// it is delivered only if the transaction commits; otherwise it is
+    /// delivered immediately.
+    pub fn notify(&self, channel_name: &str, payload: &str) -> Result<(), Error> {
+        let hub = self
+            .notification_hub
+            .ok_or(Error::NotificationsUnavailable)?;
+        let notification = Notification {
+            channel: channel_name.to_string(),
+            payload: payload.to_string(),
+        };
+        if self.in_txn() {
+            self.pending_notifications.borrow_mut().push(notification);
+        } else {
+            hub.publish(Some(notification));
+        }
+        Ok(())
+    }
+
+    /// Handle for cancelling the session's running statement from another thread.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel_handle.clone()
+    }
+
+    /// Check whether the running statement has been cancelled or has
+    /// exceeded the statement timeout.  Long-running work should call this
+    /// periodically (for example, between batches of rows) and return the
+    /// error so that `execute()` can abort the transaction.
+    pub fn check_cancelled(&self) -> Result<(), Error> {
+        if self.cancel_handle.is_cancelled() {
+            return Err(Error::QueryCancelled);
+        }
+        match self.deadline.get() {
+            Some(deadline) if Instant::now() >= deadline => Err(Error::StatementTimeout),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn in_txn(&self) -> bool {
+        self.txn_id.get().is_some()
+    }
+
+    pub fn begin(&mut self) -> Result<(), Error> {
+        if self.txn_id.get().is_some() {
+            return Err(Error::TxnAlreadyInProgress);
+        }
+        self.txn_id.set(Some(self.store.begin_txn()));
+        Ok(())
+    }
+
+    pub fn commit(&mut self) -> Result<(), Error> {
+        // The kvs discards the transaction even if commit validation fails,
+        // so the session no longer has an open transaction either way.
+        let txn_id = self.txn_id.take().ok_or(Error::NoTxnInProgress)?;
+        let notifications = self.pending_notifications.take();
+        self.store.commit_txn(txn_id)?;
+        if let Some(hub) = self.notification_hub {
+            hub.publish(notifications);
+        }
+        Ok(())
+    }
+
+    pub fn rollback(&mut self) -> Result<(), Error> {
+        let txn_id = self.txn_id.take().ok_or(Error::NoTxnInProgress)?;
+        self.pending_notifications.borrow_mut().clear();
+        self.store.abort_txn(txn_id).map_err(From::from)
+    }
+
+    /// Execute `f` within the session's open transaction, if any.
+    /// Otherwise, execute `f` in a new transaction that commits on success
+    /// and aborts on failure.
+    ///
+    /// If `f` fails because the statement was cancelled or timed out,
+    /// the session's open transaction is aborted as well.
+    ///
+    /// Calls nested inside `f` run as part of the same statement: they share
+    /// its deadline, and only the outermost call clears a cancellation.
+    /// A cancellation requested between statements applies to the next one.
+    pub fn execute<F, R>(&self, mut f: F) -> Result<R, Error>
+    where
+        F: FnMut(TxnId) -> Result<R, Error>,
+    {
+        let is_outermost = self.execute_depth.get() == 0;
+        if is_outermost {
+            self.deadline.set(
+                self.settings
+                    .statement_timeout
+                    .map(|timeout| Instant::now() + timeout),
+            );
+        }
+        self.execute_depth.set(self.execute_depth.get() + 1);
+
+        let result = {
+            // Restore the session on the way out, even if `f` panics.
+            let _guard = ExecuteGuard {
+                session: self,
+                is_outermost,
+            };
+            match self.txn_id.get() {
+                Some(txn_id) => f(txn_id),
+                None => self.store.with_txn(f),
+            }
+        };
+        if !is_outermost {
+            return result;
+        }
+
+        if let Err(Error::QueryCancelled) | Err(Error::StatementTimeout) = result {
+            if let Some(txn_id) = self.txn_id.take() {
+                self.pending_notifications.borrow_mut().clear();
+                // The txn is gone either way, and the caller needs to know
+                // why the statement failed rather than why the abort did.
+                let _ = self.store.abort_txn(txn_id);
+            }
+        }
+
+        result
+    }
+}
+
+// Leaves an `execute()` call, so the next statement starts with no deadline
+// and no pending cancellation once the outermost call returns or unwinds.
+struct ExecuteGuard<'s, 'a> {
+    session: &'s Session<'a>,
+    is_outermost: bool,
+}
+
+impl Drop for ExecuteGuard<'_, '_> {
+    fn drop(&mut self) {
+        let session = self.session;
+        session.execute_depth.set(session.execute_depth.get() - 1);
+        if self.is_outermost {
+            session.deadline.set(None);
+            session.cancel_handle.reset();
+        }
+    }
+}
+
+impl<'a> Drop for Session<'a> {
+    fn drop(&mut self) {
+        // Don't leave an abandoned transaction holding write locks.
+        if let Some(txn_id) = self.txn_id.take() {
+            let _ = self.store.abort_txn(txn_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kvs;
+    use crate::rdbms::Catalog;
+
+    #[test]
+    fn test_commit_explicit_txn() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        let mut session = Session::new(&store);
+        session.begin().expect("Could not begin txn");
+        session
+            .execute(|txn_id| catalog.create_database(txn_id, "testdb"))
+            .expect("Could not create database");
+        session.commit().expect("Could not commit txn");
+        assert!(!session.in_txn());
+
+        let result = session.execute(|txn_id| catalog.get_database_meta(txn_id, "testdb"));
+        assert!(result.is_ok(), "Error occurred: {:?}", result.err());
+    }
+
+    #[test]
+    fn test_rollback_explicit_txn() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        let mut session = Session::new(&store);
+        session.begin().expect("Could not begin txn");
+        session
+            .execute(|txn_id| catalog.create_database(txn_id, "testdb"))
+            .expect("Could not create database");
+        session.rollback().expect("Could not rollback txn");
+
+        let result = session.execute(|txn_id| catalog.get_database_meta(txn_id, "testdb"));
+        assert_eq!(
+            result,
+            Err(Error::DatabaseDoesNotExist {
+                db: "testdb".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_notify_delivered_after_commit() {
+        let store = Store::new();
+        let hub = NotificationHub::new();
+        let mut listener = Session::new(&store);
+        listener.set_notification_hub(&hub);
+        let receiver = listener.listen("jobs").expect("Could not listen");
+
+        let mut sender = Session::new(&store);
+        sender.set_notification_hub(&hub);
+        sender.begin().expect("Could not begin txn");
+        sender.notify("jobs", "a").expect("Could not notify");
+        sender.notify("other", "b").expect("Could not notify");
+        assert!(receiver.try_recv().is_err());
+
+        sender.commit().expect("Could not commit txn");
+        let received: Vec<Notification> = receiver.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![Notification {
+                channel: "jobs".to_string(),
+                payload: "a".to_string(),
+            }]
+        );
+
+        sender.notify("jobs", "c").expect("Could not notify");
+        assert_eq!(receiver.try_recv().map(|n| n.payload), Ok("c".to_string()));
+    }
+
+    #[test]
+    fn test_notify_discarded_on_rollback() {
+        let store = Store::new();
+        let hub = NotificationHub::new();
+        let mut session = Session::new(&store);
+        session.set_notification_hub(&hub);
+        let receiver = session.listen("jobs").expect("Could not listen");
+
+        session.begin().expect("Could not begin txn");
+        session.notify("jobs", "a").expect("Could not notify");
+        session.rollback().expect("Could not rollback txn");
+        session.begin().expect("Could not begin txn");
+        session.commit().expect("Could not commit txn");
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_notify_without_hub() {
+        let store = Store::new();
+        let session = Session::new(&store);
+        assert_eq!(
+            session.listen("jobs").err(),
+            Some(Error::NotificationsUnavailable)
+        );
+        assert_eq!(
+            session.notify("jobs", "a"),
+            Err(Error::NotificationsUnavailable)
+        );
+    }
+
+    #[test]
+    fn test_begin_already_in_txn() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        session.begin().expect("Could not begin txn");
+        assert_eq!(session.begin(), Err(Error::TxnAlreadyInProgress));
+    }
+
+    #[test]
+    fn test_commit_and_rollback_without_txn() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        assert_eq!(session.commit(), Err(Error::NoTxnInProgress));
+        assert_eq!(session.rollback(), Err(Error::NoTxnInProgress));
+    }
+
+    #[test]
+    fn test_write_conflict_is_retryable() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        let mut s1 = Session::new(&store);
+        let mut s2 = Session::new(&store);
+        s1.begin().expect("Could not begin txn");
+        s2.begin().expect("Could not begin txn");
+        s1.execute(|txn_id| catalog.create_database(txn_id, "foo"))
+            .expect("Could not create database");
+        let result = s2.execute(|txn_id| catalog.create_database(txn_id, "bar"));
+        assert_eq!(result, Err(Error::KvsError(kvs::Error::WriteWriteConflict)));
+        assert!(result.unwrap_err().is_retryable());
+        s2.rollback().expect("Could not rollback txn");
+        s1.commit().expect("Could not commit txn");
+    }
+
+    #[test]
+    fn test_drop_session_aborts_open_txn() {
+        let store = Store::new();
+        let catalog = Catalog::new(&store);
+        {
+            let mut session = Session::new(&store);
+            session.begin().expect("Could not begin txn");
+            session
+                .execute(|txn_id| catalog.create_database(txn_id, "foo"))
+                .expect("Could not create database");
+        }
+        let session = Session::new(&store);
+        let result = session.execute(|txn_id| catalog.create_database(txn_id, "foo"));
+        assert!(result.is_ok(), "Error occurred: {:?}", result.err());
+    }
+
+    #[test]
+    fn test_use_database() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        assert_eq!(session.current_database(), None);
+        assert_eq!(
+            session.resolve_database(None),
+            Err(Error::NoDatabaseSelected)
+        );
+
+        session
+            .execute(|txn_id| session.catalog.create_database(txn_id, "testdb"))
+            .expect("Could not create database");
+        session
+            .use_database("testdb")
+            .expect("Could not use database");
+        assert_eq!(session.current_database(), Some("testdb"));
+        assert_eq!(session.resolve_database(None), Ok("testdb"));
+        assert_eq!(session.resolve_database(Some("other")), Ok("other"));
+    }
+
+    #[test]
+    fn test_use_database_does_not_exist() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        assert_eq!(
+            session.use_database("notexist"),
+            Err(Error::DatabaseDoesNotExist {
+                db: "notexist".to_string()
+            })
+        );
+        assert_eq!(session.current_database(), None);
+    }
+
+    #[test]
+    fn test_settings() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        assert_eq!(*session.settings(), SessionSettings::default());
+        session.settings_mut().sort_mem_budget = 1024;
+        assert_eq!(session.settings().sort_mem_budget, 1024);
+    }
+
+    #[test]
+    fn test_temp_tables() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        session
+            .create_temp_table("tmp")
+            .expect("Could not create temp table");
+        assert_eq!(
+            session.create_temp_table("tmp"),
+            Err(Error::TableAlreadyExists {
+                db: None,
+                table: "tmp".to_string()
+            })
+        );
+        session
+            .create_temp_column("tmp", "foo", DataType::Int64)
+            .expect("Could not create temp column");
+        assert_eq!(
+            session.create_temp_column("tmp", "foo", DataType::Int64),
+            Err(Error::ColumnAlreadyExists {
+                db: None,
+                table: "tmp".to_string(),
+                column: "foo".to_string()
+            })
+        );
+
+        let col_names: Vec<String> = session
+            .get_temp_table_meta("tmp")
+            .expect("Could not get temp table meta")
+            .iter_col_names()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(col_names, vec!["foo"]);
+        assert_eq!(
+            session.get_temp_column_meta("tmp", "foo"),
+            Ok(&ColumnMeta::new(DataType::Int64))
+        );
+
+        session
+            .drop_temp_table("tmp")
+            .expect("Could not drop temp table");
+        assert_eq!(
+            session.get_temp_table_meta("tmp"),
+            Err(Error::TableDoesNotExist {
+                db: None,
+                table: "tmp".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_temp_tables_not_shared_between_sessions() {
+        let store = Store::new();
+        let mut s1 = Session::new(&store);
+        let s2 = Session::new(&store);
+        s1.create_temp_table("tmp")
+            .expect("Could not create temp table");
+        assert_eq!(
+            s2.get_temp_table_meta("tmp"),
+            Err(Error::TableDoesNotExist {
+                db: None,
+                table: "tmp".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_cancel_statement() {
+        let store = Store::new();
+        let session = Session::new(&store);
+        let handle = session.cancel_handle();
+        let result: Result<(), Error> = session.execute(|_| {
+            session.check_cancelled()?;
+            handle.cancel();
+            session.check_cancelled()
+        });
+        assert_eq!(result, Err(Error::QueryCancelled));
+
+        // cancellation applies only to the statement that was running
+        let result = session.execute(|_| session.check_cancelled());
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_cancel_before_statement() {
+        let store = Store::new();
+        let session = Session::new(&store);
+        session.cancel_handle().cancel();
+        let result = session.execute(|_| session.check_cancelled());
+        assert_eq!(result, Err(Error::QueryCancelled));
+
+        let result = session.execute(|_| session.check_cancelled());
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_cancel_nested_statement() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        session.settings_mut().statement_timeout = Some(Duration::from_secs(3600));
+        let handle = session.cancel_handle();
+        let result: Result<(), Error> = session.execute(|_| {
+            handle.cancel();
+            // a nested call neither clears the cancellation nor the deadline
+            session.execute(|_| Ok(()))?;
+            assert!(session.deadline.get().is_some());
+            session.check_cancelled()
+        });
+        assert_eq!(result, Err(Error::QueryCancelled));
+        assert_eq!(session.deadline.get(), None);
+    }
+
+    #[test]
+    fn test_panic_in_statement_resets_session() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        session.settings_mut().statement_timeout = Some(Duration::from_secs(3600));
+        let handle = session.cancel_handle();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            session.execute(|_| -> Result<(), Error> {
+                handle.cancel();
+                session.execute(|_| -> Result<(), Error> { panic!("statement failed") })
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(session.execute_depth.get(), 0);
+        assert_eq!(session.deadline.get(), None);
+
+        let result = session.execute(|_| session.check_cancelled());
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_statement_timeout() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        session.settings_mut().statement_timeout = Some(Duration::from_secs(0));
+        let result = session.execute(|_| session.check_cancelled());
+        assert_eq!(result, Err(Error::StatementTimeout));
+
+        session.settings_mut().statement_timeout = Some(Duration::from_secs(3600));
+        let result = session.execute(|_| session.check_cancelled());
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_cancel_aborts_explicit_txn() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        let handle = session.cancel_handle();
+        session.begin().expect("Could not begin txn");
+        let result = session.execute(|txn_id| {
+            session.catalog.create_database(txn_id, "testdb")?;
+            handle.cancel();
+            session.check_cancelled()
+        });
+        assert_eq!(result, Err(Error::QueryCancelled));
+        assert!(!session.in_txn());
+
+        let result = session.execute(|txn_id| session.catalog.get_database_meta(txn_id, "testdb"));
+        assert_eq!(
+            result,
+            Err(Error::DatabaseDoesNotExist {
+                db: "testdb".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_show_and_describe() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        session
+            .execute(|txn_id| {
+                let catalog = &session.catalog;
+                catalog.create_database(txn_id, "db1")?;
+                catalog.create_database(txn_id, "db2")?;
+                catalog.create_table(txn_id, "db1", "foo")?;
+                catalog.create_table(txn_id, "db1", "bar")?;
+                catalog.create_column(txn_id, "db1", "foo", "x", DataType::Int64)?;
+                catalog.create_column(txn_id, "db1", "foo", "y", DataType::Int64)
+            })
+            .expect("Could not create schema");
+
+        assert_eq!(
+            session.show_databases(),
+            Ok(vec!["db1".to_string(), "db2".to_string()])
+        );
+        assert_eq!(session.show_tables(None), Err(Error::NoDatabaseSelected));
+        assert_eq!(session.show_tables(Some("db2")), Ok(vec![]));
+
+        session.use_database("db1").expect("Could not use database");
+        assert_eq!(
+            session.show_tables(None),
+            Ok(vec!["bar".to_string(), "foo".to_string()])
+        );
+        assert_eq!(
+            session.describe_table(None, "foo"),
+            Ok(vec![
+                ("x".to_string(), ColumnMeta::new(DataType::Int64)),
+                ("y".to_string(), ColumnMeta::new(DataType::Int64)),
+            ])
+        );
+        assert_eq!(
+            session.describe_table(None, "notexist"),
+            Err(Error::TableDoesNotExist {
+                db: Some("db1".to_string()),
+                table: "notexist".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_check_privilege() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        session
+            .execute(|txn_id| {
+                let catalog = &session.catalog;
+                catalog.create_database(txn_id, "testdb")?;
+                catalog.create_user(txn_id, "alice", "secret")?;
+                catalog.grant(txn_id, "alice", Privilege::Select, "testdb", None)
+            })
+            .expect("Could not create schema");
+
+        // embedded sessions are not subject to access control
+        assert_eq!(
+            session.check_privilege(Privilege::Ddl, "testdb", None),
+            Ok(())
+        );
+
+        assert_eq!(
+            session.authenticate("alice", "wrong"),
+            Err(Error::AuthenticationFailed)
+        );
+        assert_eq!(session.user(), None);
+
+        session
+            .authenticate("alice", "secret")
+            .expect("Could not authenticate");
+        assert_eq!(session.user(), Some("alice"));
+        assert_eq!(
+            session.check_privilege(Privilege::Select, "testdb", Some("foo")),
+            Ok(())
+        );
+        assert_eq!(
+            session.check_privilege(Privilege::Ddl, "testdb", None),
+            Err(Error::PermissionDenied)
+        );
+    }
+
+    #[test]
+    fn test_audit_ddl() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        session
+            .create_database("testdb")
+            .expect("Could not create database");
+        session
+            .create_user("alice", "secret")
+            .expect("Could not create user");
+        session
+            .use_database("testdb")
+            .expect("Could not use database");
+
+        // auditing is disabled by default
+        session
+            .grant("alice", Privilege::Ddl, "testdb", None)
+            .expect("Could not grant privilege");
+        session
+            .create_table(None, "foo")
+            .expect("Could not create table");
+
+        session
+            .set_audit_enabled("testdb", true)
+            .expect("Could not enable auditing");
+        session
+            .authenticate("alice", "secret")
+            .expect("Could not authenticate");
+        session
+            .create_table(None, "bar")
+            .expect("Could not create table");
+        session
+            .create_column(None, "bar", "x", DataType::Int64)
+            .expect("Could not create column");
+        session
+            .grant("alice", Privilege::Select, "testdb", Some("bar"))
+            .expect("Could not grant privilege");
+        session
+            .set_audit_enabled("testdb", false)
+            .expect("Could not disable auditing");
+        session
+            .revoke("alice", Privilege::Select, "testdb", Some("bar"))
+            .expect("Could not revoke privilege");
+
+        let records = session
+            .execute(|txn_id| session.audit_log().read(txn_id, "testdb", 0))
+            .expect("Could not read audit log");
+        let summary: Vec<(Option<&str>, &str, &str)> = records
+            .iter()
+            .map(|r| (r.user.as_deref(), r.action.as_str(), r.object.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (None, "ALTER DATABASE SET AUDIT ON", "testdb"),
+                (Some("alice"), "CREATE TABLE", "bar"),
+                (Some("alice"), "ALTER TABLE ADD COLUMN", "bar.x"),
+                (Some("alice"), "GRANT", "SELECT ON testdb.bar TO alice"),
+                (Some("alice"), "ALTER DATABASE SET AUDIT OFF", "testdb"),
+            ]
+        );
+
+        let records = session
+            .execute(|txn_id| session.audit_log().read_system(txn_id, 0))
+            .expect("Could not read system audit log");
+        let summary: Vec<(Option<&str>, &str, &str)> = records
+            .iter()
+            .map(|r| (r.user.as_deref(), r.action.as_str(), r.object.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (None, "CREATE DATABASE", "testdb"),
+                (None, "CREATE USER", "alice")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_create_table_permission_denied() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        session
+            .execute(|txn_id| {
+                let catalog = &session.catalog;
+                catalog.create_database(txn_id, "testdb")?;
+                catalog.create_user(txn_id, "alice", "secret")
+            })
+            .expect("Could not create schema");
+        session
+            .authenticate("alice", "secret")
+            .expect("Could not authenticate");
+        assert_eq!(
+            session.create_table(Some("testdb"), "foo"),
+            Err(Error::PermissionDenied)
+        );
+    }
+
+    #[test]
+    fn test_vacuum() {
+        let store = Store::new();
+        let session = Session::new(&store);
+        for tbl_name in ["foo", "bar"].iter() {
+            session
+                .execute(|txn_id| {
+                    let catalog = &session.catalog;
+                    if catalog.get_database_meta(txn_id, "testdb").is_err() {
+                        catalog.create_database(txn_id, "testdb")?;
+                    }
+                    catalog.create_table(txn_id, "testdb", tbl_name)
+                })
+                .expect("Could not create table");
+        }
+
+        let stats = session.vacuum().expect("Could not vacuum");
+        assert!(stats.unlinked_versions > 0);
+        assert!(stats.unlinked_bytes > 0);
+        assert_eq!(session.vacuum(), Ok(VacuumStats::default()));
+        assert_eq!(
+            session.show_tables(Some("testdb")),
+            Ok(vec!["bar".to_string(), "foo".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_vacuum_permission_denied() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        session
+            .execute(|txn_id| session.catalog.create_user(txn_id, "alice", "secret"))
+            .expect("Could not create user");
+        session
+            .authenticate("alice", "secret")
+            .expect("Could not authenticate");
+        assert_eq!(session.vacuum(), Err(Error::PermissionDenied));
+    }
+
+    #[test]
+    fn test_unprivileged_user_is_refused() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        session
+            .create_database("testdb")
+            .expect("Could not create database");
+        session
+            .create_database("otherdb")
+            .expect("Could not create database");
+        session
+            .create_table(Some("testdb"), "foo")
+            .expect("Could not create table");
+        session
+            .create_table(Some("testdb"), "bar")
+            .expect("Could not create table");
+        session
+            .create_user("alice", "secret")
+            .expect("Could not create user");
+        session
+            .authenticate("alice", "secret")
+            .expect("Could not authenticate");
+
+        assert_eq!(session.show_databases(), Ok(vec![]));
+        assert_eq!(session.use_database("testdb"), Err(Error::PermissionDenied));
+        assert_eq!(
+            session.show_tables(Some("testdb")),
+            Err(Error::PermissionDenied)
+        );
+        assert_eq!(
+            session.describe_table(Some("testdb"), "foo"),
+            Err(Error::PermissionDenied)
+        );
+        assert_eq!(
+            session.create_database("newdb"),
+            Err(Error::PermissionDenied)
+        );
+        assert_eq!(
+            session.create_user("mallory", "secret"),
+            Err(Error::PermissionDenied)
+        );
+        assert_eq!(
+            session.grant("alice", Privilege::Ddl, "testdb", None),
+            Err(Error::PermissionDenied)
+        );
+        assert_eq!(
+            session.revoke("alice", Privilege::Ddl, "testdb", None),
+            Err(Error::PermissionDenied)
+        );
+        assert_eq!(
+            session.set_audit_enabled("testdb", false),
+            Err(Error::PermissionDenied)
+        );
+    }
+
+    #[test]
+    fn test_alter_user_password() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        for user_name in ["alice", "bob"].iter() {
+            session
+                .create_user(user_name, "secret")
+                .expect("Could not create user");
+        }
+        session
+            .alter_user_password("bob", "hunter2")
+            .expect("Could not alter password");
+
+        session
+            .authenticate("alice", "secret")
+            .expect("Could not authenticate");
+        assert_eq!(
+            session.alter_user_password("bob", "pwned"),
+            Err(Error::PermissionDenied)
+        );
+        session
+            .alter_user_password("alice", "changed")
+            .expect("Could not alter password");
+
+        assert_eq!(session.authenticate("bob", "hunter2"), Ok(()));
+        assert_eq!(
+            session.authenticate("alice", "secret"),
+            Err(Error::AuthenticationFailed)
+        );
+        assert_eq!(session.authenticate("alice", "changed"), Ok(()));
+
+        let records = session
+            .execute(|txn_id| session.audit_log().read_system(txn_id, 2))
+            .expect("Could not read system audit log");
+        let summary: Vec<(Option<&str>, &str, &str)> = records
+            .iter()
+            .map(|r| (r.user.as_deref(), r.action.as_str(), r.object.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (None, "ALTER USER PASSWORD", "bob"),
+                (Some("alice"), "ALTER USER PASSWORD", "alice"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_show_only_granted_objects() {
+        let store = Store::new();
+        let mut session = Session::new(&store);
+        for db_name in ["testdb", "otherdb"].iter() {
+            session
+                .create_database(db_name)
+                .expect("Could not create database");
+        }
+        for tbl_name in ["foo", "bar"].iter() {
+            session
+                .create_table(Some("testdb"), tbl_name)
+                .expect("Could not create table");
+        }
+        session
+            .create_user("alice", "secret")
+            .expect("Could not create user");
+        session
+            .grant("alice", Privilege::Select, "testdb", Some("foo"))
+            .expect("Could not grant privilege");
+        session
+            .authenticate("alice", "secret")
+            .expect("Could not authenticate");
+
+        assert_eq!(session.show_databases(), Ok(vec!["testdb".to_string()]));
+        session
+            .use_database("testdb")
+            .expect("Could not use database");
+        assert_eq!(session.show_tables(None), Ok(vec!["foo".to_string()]));
+        assert_eq!(session.describe_table(None, "foo"), Ok(vec![]));
+        assert_eq!(
+            session.describe_table(None, "bar"),
+            Err(Error::PermissionDenied)
+        );
+
+        // a grant on the table does not allow granting privileges on the database
+        assert_eq!(
+            session.grant("alice", Privilege::Ddl, "testdb", None),
+            Err(Error::PermissionDenied)
+        );
+    }
+}